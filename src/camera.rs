@@ -1,25 +1,160 @@
 mod motion_camera;
+mod process;
 mod fake_camera;
+mod test_pattern;
+mod v4l2;
+mod v4l2_camera;
+mod command_camera;
+mod rtsp_camera;
 
-use std::io::Result;
+use async_trait::async_trait;
+use std::io::{Error, ErrorKind, Result};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::watch;
 
 use motion_camera::MotionCamera;
 use fake_camera::FakeCamera;
+use v4l2_camera::V4l2Camera;
+use command_camera::CommandCamera;
+use rtsp_camera::RtspCamera;
 
 use crate::fs::Fs;
+use crate::servo::{Angles, Servo};
+use crate::settings::{CameraSettings, Settings};
 
-pub trait Camera {
-    fn is_active(&self) -> bool;
-    fn start(&self) -> Result<()>;
-    fn stop(&self) -> Result<()>;
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Parameter {
+    Framerate,
+    Quality,
+    Threshold,
+}
+
+pub mod capabilities {
+    pub const FRAMERATE: u32 = 1 << 0;
+    pub const QUALITY: u32 = 1 << 1;
+    pub const THRESHOLD: u32 = 1 << 2;
+    pub const DETECTION: u32 = 1 << 3;
+    pub const SNAPSHOT: u32 = 1 << 4;
+    pub const RECORDINGS: u32 = 1 << 5;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum State {
+    Stopped,
+    Starting,
+    Running,
+    Stopping,
+    Failed(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Status {
+    pub state: State,
+    pub width: u32,
+    pub height: u32,
+    pub framerate: u32,
+    pub capabilities: u32,
+}
+
+impl State {
+    pub fn is_active(&self) -> bool {
+        matches!(self, State::Starting | State::Running)
+    }
+}
+
+#[async_trait]
+pub trait Camera: Send + Sync {
+    async fn start(&self) -> Result<()>;
+    async fn stop(&self) -> Result<()>;
+    fn status(&self) -> Status;
     fn port(&self) -> u16;
+    fn stream_url(&self) -> Option<String>;
+    async fn get_parameter(&self, parameter: Parameter) -> Result<i32>;
+    async fn set_parameter(&self, parameter: Parameter, value: i32) -> Result<()>;
+    async fn is_detection_enabled(&self) -> Result<bool>;
+    async fn set_detection_enabled(&self, enabled: bool) -> Result<()>;
+    async fn snapshot(&self) -> Result<Vec<u8>>;
+    fn recordings_dir(&self) -> Option<PathBuf>;
 }
 
-pub fn init_camera(fs: Fs) -> Result<Box<dyn Camera>> {
-    if MotionCamera::is_available() {
-        let camera = MotionCamera::new(fs)?;
-        Ok(Box::new(camera))
-    } else {
-        Ok(Box::new(FakeCamera{}))
+type Constructor = fn(&Fs, CameraSettings, Option<watch::Receiver<Angles>>) -> Result<Box<dyn Camera>>;
+
+struct Backend {
+    name: &'static str,
+    check_available: fn(&CameraSettings) -> Result<()>,
+    create: Constructor,
+}
+
+const BACKENDS: [Backend; 5] = [
+    Backend {
+        name: "motion",
+        check_available: MotionCamera::check_available,
+        create: |fs, settings, _| Ok(Box::new(MotionCamera::new(fs, settings)?)),
+    },
+    Backend {
+        name: "v4l2",
+        check_available: V4l2Camera::check_available,
+        create: |_, settings, _| Ok(Box::new(V4l2Camera::new(settings)?)),
+    },
+    Backend {
+        name: "command",
+        check_available: CommandCamera::check_available,
+        create: |fs, settings, _| Ok(Box::new(CommandCamera::new(fs, settings)?)),
+    },
+    Backend {
+        name: "rtsp",
+        check_available: RtspCamera::check_available,
+        create: |_, settings, _| Ok(Box::new(RtspCamera::new(settings)?)),
+    },
+    Backend {
+        name: "fake",
+        check_available: |_| Ok(()),
+        create: |_, settings, servo_angles| Ok(Box::new(FakeCamera::new(settings, servo_angles)?)),
+    },
+];
+
+pub struct CameraInstance {
+    pub id: u32,
+    pub backend: &'static str,
+    pub rig: Option<u32>,
+    pub camera: Arc<dyn Camera>,
+}
+
+pub fn init_cameras(fs: &Fs, settings: &Settings, backend: Option<&str>, servo: Option<&Servo>) -> Result<Vec<CameraInstance>> {
+    let cameras = settings.cameras()?;
+    for (index, camera) in cameras.iter().enumerate() {
+        if cameras[..index].iter().any(|c| c.id == camera.id) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Camera id {} is used more than once", camera.id)));
+        }
+    }
+
+    cameras.into_iter()
+        .map(|camera_settings| {
+            let servo_angles = camera_settings.rig.and_then(|rig| servo?.angles(rig));
+            init_camera(fs, camera_settings, backend, servo_angles)
+        })
+        .collect()
+}
+
+fn init_camera(fs: &Fs, mut settings: CameraSettings, backend: Option<&str>, servo_angles: Option<watch::Receiver<Angles>>) -> Result<CameraInstance> {
+    if let Some(name) = backend {
+        settings.backend = name.to_owned();
     }
-}
\ No newline at end of file
+
+    let id = settings.id;
+    let backend = BACKENDS.iter()
+        .find(|b| b.name == settings.backend)
+        .ok_or_else(|| {
+            let names = BACKENDS.iter().map(|b| b.name).collect::<Vec<_>>().join(", ");
+            Error::new(ErrorKind::InvalidInput, format!("Unknown backend {} for camera {}, expected one of: {}", settings.backend, id, names))
+        })?;
+
+    (backend.check_available)(&settings)
+        .map_err(|e| Error::new(e.kind(), format!("Camera {} backend {} is not available: {}", id, backend.name, e)))?;
+
+    info!("Using {} backend for camera {}", backend.name, id);
+    let rig = settings.rig;
+    let camera = (backend.create)(fs, settings, servo_angles)?;
+    Ok(CameraInstance { id, backend: backend.name, rig, camera: Arc::from(camera) })
+}
@@ -0,0 +1,110 @@
+use async_trait::async_trait;
+use std::io::{Error, ErrorKind, Result};
+use std::path::PathBuf;
+
+use super::{Camera, Parameter, Status};
+use super::process::SupervisedProcess;
+use crate::fs::Fs;
+use crate::settings::CameraSettings;
+
+pub struct CommandCamera {
+    process: SupervisedProcess,
+    port: u16,
+    width: u32,
+    height: u32,
+    framerate: u32,
+}
+
+impl CommandCamera {
+    pub fn new(fs: &Fs, settings: CameraSettings) -> Result<Self> {
+        let command = expand_command(&command(&settings)?, &settings);
+        info!("Camera {} command: {}", settings.id, command);
+
+        let name = format!("command-{}", settings.id);
+        let args = [String::from("-c"), command];
+        Ok(CommandCamera {
+            process: SupervisedProcess::new(&name, "sh", &args, fs.camera_pid_file(&name)?),
+            port: settings.stream_port,
+            width: settings.width,
+            height: settings.height,
+            framerate: settings.framerate,
+        })
+    }
+
+    pub fn check_available(settings: &CameraSettings) -> Result<()> {
+        command(settings).map(|_| ())
+    }
+}
+
+#[async_trait]
+impl Camera for CommandCamera {
+    async fn start(&self) -> Result<()> {
+        self.process.start()
+    }
+
+    async fn stop(&self) -> Result<()> {
+        self.process.stop()
+    }
+
+    fn status(&self) -> Status {
+        Status {
+            state: self.process.state(),
+            width: self.width,
+            height: self.height,
+            framerate: self.framerate,
+            capabilities: 0,
+        }
+    }
+
+    fn port(&self) -> u16 {
+        self.port
+    }
+
+    fn stream_url(&self) -> Option<String> {
+        None
+    }
+
+    async fn get_parameter(&self, parameter: Parameter) -> Result<i32> {
+        Err(unsupported(parameter))
+    }
+
+    async fn set_parameter(&self, parameter: Parameter, _value: i32) -> Result<()> {
+        Err(unsupported(parameter))
+    }
+
+    async fn is_detection_enabled(&self) -> Result<bool> {
+        Ok(false)
+    }
+
+    async fn set_detection_enabled(&self, _enabled: bool) -> Result<()> {
+        Err(Error::new(ErrorKind::Unsupported, "Command camera has no motion detection"))
+    }
+
+    async fn snapshot(&self) -> Result<Vec<u8>> {
+        Err(Error::new(ErrorKind::Unsupported, "Command camera does not support snapshots"))
+    }
+
+    fn recordings_dir(&self) -> Option<PathBuf> {
+        None
+    }
+}
+
+fn command(settings: &CameraSettings) -> Result<String> {
+    settings.command.as_ref()
+        .filter(|command| !command.trim().is_empty())
+        .cloned()
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "camera_command is not set"))
+}
+
+fn expand_command(command: &str, settings: &CameraSettings) -> String {
+    command
+        .replace("%p", &settings.stream_port.to_string())
+        .replace("%d", &settings.video_device.display().to_string())
+        .replace("%w", &settings.width.to_string())
+        .replace("%h", &settings.height.to_string())
+        .replace("%f", &settings.framerate.to_string())
+}
+
+fn unsupported(parameter: Parameter) -> Error {
+    Error::new(ErrorKind::Unsupported, format!("Command camera does not support {:?}", parameter))
+}
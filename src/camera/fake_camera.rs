@@ -1,40 +1,172 @@
-use std::path::Path;
+use async_trait::async_trait;
+use bytes::Bytes;
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{broadcast, watch};
 
-use super::Camera;
+use super::{self as camera, capabilities, Camera, Parameter, Status};
+use super::test_pattern::TestPattern;
+use crate::mjpeg;
+use crate::servo::Angles;
+use crate::settings::CameraSettings;
 
+const FRAME_WIDTH: u16 = 640;
+const FRAME_HEIGHT: u16 = 480;
+const FRAME_BUFFER_SIZE: usize = 4;
+const BAR_SPEED: usize = 4;
+const TEXT_SCALE: usize = 3;
+const TEXT_MARGIN: usize = 8;
 
-pub struct FakeCamera;
+pub struct FakeCamera {
+    state: Arc<State>,
+    port: u16,
+}
+
+struct State {
+    framerate: AtomicI32,
+    quality: AtomicI32,
+    threshold: AtomicI32,
+    detection_enabled: AtomicBool,
+    is_active: AtomicBool,
+    servo_angles: Option<watch::Receiver<Angles>>,
+}
 
 impl FakeCamera {
-    fn get_lock_path() -> &'static Path {
-        Path::new("camera_active")
+    pub fn new(settings: CameraSettings, servo_angles: Option<watch::Receiver<Angles>>) -> std::io::Result<Self> {
+        let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, settings.stream_port))?;
+        listener.set_nonblocking(true)?;
+        let listener = tokio::net::TcpListener::from_std(listener)?;
+
+        let (frames, _) = broadcast::channel(FRAME_BUFFER_SIZE);
+        tokio::spawn(mjpeg::serve(listener, frames.clone()));
+        info!("Serving a test pattern for camera {} on port {}", settings.id, settings.stream_port);
+
+        let state = Arc::new(State {
+            framerate: AtomicI32::new(settings.framerate as i32),
+            quality: AtomicI32::new(settings.stream_quality as i32),
+            threshold: AtomicI32::new(settings.threshold as i32),
+            detection_enabled: AtomicBool::new(true),
+            is_active: AtomicBool::new(false),
+            servo_angles,
+        });
+
+        let generator_state = state.clone();
+        std::thread::spawn(move || generate_frames(generator_state, frames));
+
+        Ok(FakeCamera { state, port: settings.stream_port })
+    }
+
+    fn parameter(&self, parameter: Parameter) -> &AtomicI32 {
+        match parameter {
+            Parameter::Framerate => &self.state.framerate,
+            Parameter::Quality => &self.state.quality,
+            Parameter::Threshold => &self.state.threshold,
+        }
     }
 }
 
+#[async_trait]
 impl Camera for FakeCamera {
-    fn is_active(&self) -> bool {
-        Self::get_lock_path().exists()
+    fn status(&self) -> Status {
+        let state = if self.state.is_active.load(Ordering::Acquire) { camera::State::Running } else { camera::State::Stopped };
+        Status {
+            state,
+            width: FRAME_WIDTH as u32,
+            height: FRAME_HEIGHT as u32,
+            framerate: self.state.framerate.load(Ordering::Acquire).max(0) as u32,
+            capabilities: capabilities::FRAMERATE | capabilities::QUALITY | capabilities::THRESHOLD
+                | capabilities::DETECTION | capabilities::SNAPSHOT,
+        }
     }
 
     fn port(&self) -> u16 {
-        8981
+        self.port
     }
 
-    fn start(&self) -> std::io::Result<()> {
-        let path = Self::get_lock_path();
-        if !path.exists() {
-            std::fs::File::create(path)?;
-        }
+    fn stream_url(&self) -> Option<String> {
+        None
+    }
+
+    async fn start(&self) -> std::io::Result<()> {
+        self.state.is_active.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    async fn stop(&self) -> std::io::Result<()> {
+        self.state.is_active.store(false, Ordering::Release);
+        Ok(())
+    }
+
+    async fn get_parameter(&self, parameter: Parameter) -> std::io::Result<i32> {
+        Ok(self.parameter(parameter).load(Ordering::Acquire))
+    }
+
+    async fn set_parameter(&self, parameter: Parameter, value: i32) -> std::io::Result<()> {
+        self.parameter(parameter).store(value, Ordering::Release);
+        Ok(())
+    }
+
+    async fn is_detection_enabled(&self) -> std::io::Result<bool> {
+        Ok(self.state.detection_enabled.load(Ordering::Acquire))
+    }
 
+    async fn set_detection_enabled(&self, enabled: bool) -> std::io::Result<()> {
+        self.state.detection_enabled.store(enabled, Ordering::Release);
         Ok(())
     }
 
-    fn stop(&self) -> std::io::Result<()> {
-        let path = Self::get_lock_path();
-        if path.exists() {
-            std::fs::remove_file(path)
-        } else {
-            Ok(())
+    async fn snapshot(&self) -> std::io::Result<Vec<u8>> {
+        self.state.render(0)
+    }
+
+    fn recordings_dir(&self) -> Option<PathBuf> {
+        None
+    }
+}
+
+impl State {
+    fn render(&self, frame_number: usize) -> std::io::Result<Vec<u8>> {
+        let mut pattern = TestPattern::new(FRAME_WIDTH, FRAME_HEIGHT);
+        pattern.draw_bars(frame_number * BAR_SPEED);
+        pattern.draw_text(TEXT_MARGIN, TEXT_MARGIN, TEXT_SCALE, &current_time());
+
+        if let Some(angles) = &self.servo_angles {
+            let angles = *angles.borrow();
+            let y = pattern.height() as usize - TEXT_MARGIN - 9 * TEXT_SCALE;
+            pattern.draw_text(TEXT_MARGIN, y, TEXT_SCALE, &format!("PAN {} TILT {}", angles.pan, angles.tilt));
+        }
+
+        pattern.encode(self.quality.load(Ordering::Acquire).clamp(1, 100) as u8)
+    }
+}
+
+fn generate_frames(state: Arc<State>, frames: broadcast::Sender<Bytes>) {
+    let mut frame_number = 0_usize;
+    while Arc::strong_count(&state) > 1 {
+        let framerate = state.framerate.load(Ordering::Acquire).max(1) as u32;
+        std::thread::sleep(Duration::from_secs(1) / framerate);
+
+        if !state.is_active.load(Ordering::Acquire) || frames.receiver_count() == 0 {
+            continue;
         }
+
+        match state.render(frame_number) {
+            Ok(frame) => { frames.send(Bytes::from(frame)).unwrap_or_default(); },
+            Err(e) => error!("Failed to render test pattern: {}", e),
+        }
+        frame_number = frame_number.wrapping_add(1);
     }
-}
\ No newline at end of file
+}
+
+fn current_time() -> String {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) as libc::time_t;
+    let mut time = unsafe { std::mem::zeroed::<libc::tm>() };
+    unsafe { libc::localtime_r(&seconds, &mut time); }
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        time.tm_year + 1900, time.tm_mon + 1, time.tm_mday,
+        time.tm_hour, time.tm_min, time.tm_sec)
+}
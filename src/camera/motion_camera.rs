@@ -1,86 +1,262 @@
-use std::{process::{Command, Stdio}, io::BufRead};
+use async_trait::async_trait;
+use std::process::{Command, Stdio};
 use std::io::{Result, Error, ErrorKind};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
 
-use super::Camera;
+use super::{capabilities, Camera, Parameter, Status};
+use super::process::SupervisedProcess;
+use crate::config::Config;
 use crate::fs;
+use crate::settings::CameraSettings;
+
+const WEBCONTROL_TIMEOUT: u64 = 2000;
+const WEBCONTROL_CAMERA: u32 = 0;
+const LAST_SNAPSHOT: &str = "lastsnap.jpg";
+const SNAPSHOT_POLL_INTERVAL: u64 = 100;
+const SNAPSHOT_POLL_ATTEMPTS: u32 = 30;
+
+const MOTION_DEFAULTS: [(&str, &str); 27] = [
+    ("daemon", "off"),
+    ("setup_mode", "off"),
+    ("log_level", "6"),
+    ("log_type", "all"),
+    ("v4l2_palette", "17"),
+    ("threshold_tune", "off"),
+    ("noise_level", "32"),
+    ("noise_tune", "on"),
+    ("despeckle_filter", "EedDl"),
+    ("minimum_motion_frames", "1"),
+    ("event_gap", "3"),
+    ("output_pictures", "off"),
+    ("quality", "75"),
+    ("ffmpeg_output_movies", "on"),
+    ("ffmpeg_video_codec", "mkv"),
+    ("locate_motion_mode", "off"),
+    ("text_right", "%Y-%m-%d\\n%T-%q"),
+    ("snapshot_filename", "%v-%Y%m%d%H%M%S-snapshot"),
+    ("picture_filename", "%v-%Y%m%d%H%M%S-%q"),
+    ("movie_filename", "%v-%Y%m%d%H%M%S"),
+    ("stream_motion", "on"),
+    ("stream_maxrate", "20"),
+    ("stream_localhost", "on"),
+    ("webcontrol_localhost", "on"),
+    ("webcontrol_html_output", "off"),
+    ("webcontrol_parms", "2"),
+    ("quiet", "on"),
+];
 
 pub struct MotionCamera {
-    fs: fs::Fs,
+    process: SupervisedProcess,
     port: u16,
+    width: u32,
+    height: u32,
+    framerate: AtomicU32,
+    target_dir: PathBuf,
+    webcontrol: WebControl,
+}
+
+struct WebControl {
+    address: SocketAddr,
 }
 
 impl MotionCamera {
-    pub fn new (fs: fs::Fs) -> Result<Self> {
-        let port = Self::get_port(&fs);
-        port.and_then(|p| {
-            println!("Camera port {}", p);
-            Ok(MotionCamera {fs, port: p})
+    pub fn new(fs: &fs::Fs, settings: CameraSettings) -> Result<Self> {
+        let name = format!("motion-{}", settings.id);
+        let config_file = fs.camera_config_file(&name)?;
+        render_config(&settings, &fs.event_socket_file()?).save(&config_file)?;
+        info!("Camera {} config written to {}", settings.id, config_file.display());
+        info!("Camera {} port {}", settings.id, settings.stream_port);
+
+        let args = [String::from("-n"), String::from("-c"), config_file.display().to_string()];
+        Ok(MotionCamera {
+            process: SupervisedProcess::new(&name, "motion", &args, fs.camera_pid_file(&name)?),
+            port: settings.stream_port,
+            width: settings.width,
+            height: settings.height,
+            framerate: AtomicU32::new(settings.framerate),
+            target_dir: settings.target_dir,
+            webcontrol: WebControl::new(settings.webcontrol_port),
         })
     }
 
-    pub fn is_available() -> bool {
+    pub fn check_available(_settings: &CameraSettings) -> Result<()> {
         Command::new("motion")
             .arg("-h")
             .stdout(Stdio::null())
+            .stderr(Stdio::null())
             .status()
-            .is_ok()
-    }
-
-    fn get_port(fs: &fs::Fs) -> Result<u16> {
-        let file_path = fs.camera_config_file()?;
-        let file = std::fs::File::open(file_path)?;
-        let reader = std::io::BufReader::new(file);
-        for l in reader.lines() {
-            let line = l?;
-            if line.starts_with("stream_port")  {
-                if let Some(port_string) = line.split_whitespace().nth(1) {
-                    return port_string.parse::<u16>().map_err(|e|
-                        Error::new(ErrorKind::InvalidData, format!("Failed to parse port number from the config file.\n{}", e))
-                    )
-                }
+            .map(|_| ())
+            .map_err(|e| Error::new(e.kind(), format!("Failed to run motion: {}", e)))
+    }
+}
+
+#[async_trait]
+impl Camera for MotionCamera {
+    async fn start(&self) -> Result<()> {
+        self.process.start()
+    }
+
+    async fn stop(&self) -> Result<()> {
+        self.process.stop()
+    }
+
+    fn status(&self) -> Status {
+        Status {
+            state: self.process.state(),
+            width: self.width,
+            height: self.height,
+            framerate: self.framerate.load(Ordering::Acquire),
+            capabilities: capabilities::FRAMERATE | capabilities::QUALITY | capabilities::THRESHOLD
+                | capabilities::DETECTION | capabilities::SNAPSHOT | capabilities::RECORDINGS,
+        }
+    }
+
+    fn port(&self) -> u16 {
+        self.port
+    }
+
+    fn stream_url(&self) -> Option<String> {
+        None
+    }
+
+    async fn get_parameter(&self, parameter: Parameter) -> Result<i32> {
+        let value = self.webcontrol.get(parameter_name(parameter)).await?;
+        value.parse::<i32>().map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))
+    }
+
+    async fn set_parameter(&self, parameter: Parameter, value: i32) -> Result<()> {
+        self.webcontrol.set(parameter_name(parameter), &value.to_string()).await?;
+        if parameter == Parameter::Framerate {
+            self.framerate.store(value.max(0) as u32, Ordering::Release);
+        }
+
+        Ok(())
+    }
+
+    async fn is_detection_enabled(&self) -> Result<bool> {
+        let status = self.webcontrol.request("detection/status").await?;
+        Ok(status.contains("ACTIVE"))
+    }
+
+    async fn set_detection_enabled(&self, enabled: bool) -> Result<()> {
+        let action = if enabled { "detection/start" } else { "detection/pause" };
+        self.webcontrol.request(action).await.map(|_| ())
+    }
+
+    async fn snapshot(&self) -> Result<Vec<u8>> {
+        let snapshot_path = self.target_dir.join(LAST_SNAPSHOT);
+        let previous_snapshot = modified_time(&snapshot_path);
+        self.webcontrol.request("action/snapshot").await?;
+
+        for _ in 0..SNAPSHOT_POLL_ATTEMPTS {
+            tokio::time::sleep(Duration::from_millis(SNAPSHOT_POLL_INTERVAL)).await;
+
+            let current_snapshot = modified_time(&snapshot_path);
+            if current_snapshot.is_none() || current_snapshot == previous_snapshot {
+                continue;
+            }
+
+            let image = std::fs::read(&snapshot_path)?;
+            if image.ends_with(&[0xFF, 0xD9]) {
+                return Ok(image);
             }
         }
 
-        Err(Error::new(ErrorKind::NotFound, "Failed to find camera port setting in config file"))
+        Err(Error::new(ErrorKind::TimedOut, "Motion did not save a snapshot in time"))
+    }
+
+    fn recordings_dir(&self) -> Option<PathBuf> {
+        Some(self.target_dir.clone())
     }
 }
 
-impl Camera for MotionCamera {
-    fn is_active(&self) -> bool {
-        self.fs.camera_pid_file().map_or(false, |path| path.exists())
+impl WebControl {
+    fn new(port: u16) -> Self {
+        WebControl { address: SocketAddr::from((Ipv4Addr::LOCALHOST, port)) }
     }
 
-    fn start(&self) -> std::io::Result<()> {
-        let config_file = self.fs.camera_config_file()?;
-        let pid_file = self.fs.camera_pid_file()?;
+    async fn get(&self, name: &str) -> Result<String> {
+        let response = self.request(&format!("config/get?query={}", name)).await?;
+        response.lines()
+            .filter_map(|line| line.split_once('='))
+            .find(|(key, _)| key.trim() == name)
+            .map(|(_, value)| value.trim().to_owned())
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("Motion did not report {}", name)))
+    }
 
-        Command::new("motion")
-            .arg("-c").arg(config_file.into_os_string())
-            .arg("-p").arg(pid_file.into_os_string())
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .spawn()
-            .map(|_| ())
+    async fn set(&self, name: &str, value: &str) -> Result<()> {
+        self.request(&format!("config/set?{}={}", name, value)).await.map(|_| ())
     }
 
-    fn stop(&self) -> std::io::Result<()> {
-        let pid_file = self.fs.camera_pid_file()?;
-        let pid = std::fs::read_to_string(pid_file)?;
+    async fn request(&self, action: &str) -> Result<String> {
+        let timeout = Duration::from_millis(WEBCONTROL_TIMEOUT);
+        tokio::time::timeout(timeout, self.exchange(action)).await
+            .map_err(|_| Error::new(ErrorKind::TimedOut, format!("Webcontrol request {} timed out", action)))?
+    }
 
-        Command::new("kill")
-            .arg("-INT")
-            .arg(pid.trim())
-            .spawn()
-            .map(|_| ())
+    async fn exchange(&self, action: &str) -> Result<String> {
+        let mut stream = TcpStream::connect(self.address).await?;
+        let request = format!("GET /{}/{} HTTP/1.0\r\nHost: localhost\r\n\r\n", WEBCONTROL_CAMERA, action);
+        stream.write_all(request.as_bytes()).await?;
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+
+        let (head, body) = response.split_once("\r\n\r\n")
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Malformed webcontrol response"))?;
+        let status = head.lines().next().unwrap_or_default();
+        if !status.contains(" 200") {
+            return Err(Error::other(format!("Webcontrol request {} failed: {}", action, status)));
+        }
+
+        Ok(body.to_owned())
     }
+}
 
-    fn port(&self) -> u16 {
-        self.port
+fn render_config(settings: &CameraSettings, event_socket: &Path) -> Config {
+    let mut config = Config::new();
+    config.add_comment("Generated by eye from its settings on startup, local changes will be overwritten");
+
+    for (key, value) in MOTION_DEFAULTS.iter() {
+        config.set(key, value);
     }
+
+    config.set("videodevice", settings.video_device.display());
+    config.set("width", settings.width);
+    config.set("height", settings.height);
+    config.set("framerate", settings.framerate);
+    config.set("rotate", settings.rotation);
+    config.set("threshold", settings.threshold);
+    config.set("target_dir", settings.target_dir.display());
+    config.set("stream_port", settings.stream_port);
+    config.set("stream_quality", settings.stream_quality);
+    config.set("webcontrol_port", settings.webcontrol_port);
+
+    let hook = format!("{} --socket {}", settings.motion_hook, event_socket.display());
+    config.set("on_event_start", format!("{} event_start {} event=%v", hook, settings.id));
+    config.set("on_event_end", format!("{} event_end {} event=%v", hook, settings.id));
+    config.set("on_movie_end", format!("{} movie_end {} event=%v \"file=%f\"", hook, settings.id));
+    config.set("on_picture_save", format!("{} picture_save {} event=%v \"file=%f\"", hook, settings.id));
+    if settings.tracking.is_some() {
+        config.set("on_motion_detected", format!("{} motion_detected {} event=%v x=%K y=%L", hook, settings.id));
+    }
+
+    config
 }
 
-impl Drop for MotionCamera {
-    fn drop(&mut self) {
-        self.stop();
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn parameter_name(parameter: Parameter) -> &'static str {
+    match parameter {
+        Parameter::Framerate => "framerate",
+        Parameter::Quality => "stream_quality",
+        Parameter::Threshold => "threshold",
     }
-}
\ No newline at end of file
+}
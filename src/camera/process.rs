@@ -0,0 +1,245 @@
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+use super::State;
+
+const MIN_RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+const STABLE_RUN_TIME: Duration = Duration::from_secs(60);
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+enum ProcessCommand {
+    Start,
+    Stop,
+}
+
+pub struct SupervisedProcess {
+    commands: UnboundedSender<ProcessCommand>,
+    state: watch::Receiver<State>,
+}
+
+#[derive(Clone)]
+struct ProcessSpec {
+    name: String,
+    program: String,
+    args: Vec<String>,
+    pid_file: PathBuf,
+}
+
+struct RunningProcess {
+    child: Child,
+    started_at: Instant,
+}
+
+impl SupervisedProcess {
+    pub fn new(name: &str, program: &str, args: &[String], pid_file: PathBuf) -> Self {
+        let spec = ProcessSpec {
+            name: name.to_owned(),
+            program: program.to_owned(),
+            args: args.to_vec(),
+            pid_file,
+        };
+        remove_stale_pid_file(&spec);
+
+        let (commands, receiver) = mpsc::unbounded_channel();
+        let (state_sender, state) = watch::channel(State::Stopped);
+        tokio::spawn(async move {
+            supervise(spec, receiver, state_sender).await;
+        });
+
+        SupervisedProcess { commands, state }
+    }
+
+    pub fn state(&self) -> State {
+        self.state.borrow().clone()
+    }
+
+    pub fn start(&self) -> std::io::Result<()> {
+        self.send(ProcessCommand::Start)
+    }
+
+    pub fn stop(&self) -> std::io::Result<()> {
+        self.send(ProcessCommand::Stop)
+    }
+
+    fn send(&self, command: ProcessCommand) -> std::io::Result<()> {
+        self.commands.send(command)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::BrokenPipe, format!("Process supervisor is gone: {:?}", e.0)))
+    }
+}
+
+async fn supervise(spec: ProcessSpec, mut commands: UnboundedReceiver<ProcessCommand>, state: watch::Sender<State>) {
+    let mut should_run = false;
+    let mut process: Option<RunningProcess> = None;
+    let mut restart_at: Option<Instant> = None;
+    let mut restart_delay = MIN_RESTART_DELAY;
+
+    loop {
+        tokio::select! {
+            command = commands.recv() => match command {
+                Some(ProcessCommand::Start) => {
+                    should_run = true;
+                    restart_delay = MIN_RESTART_DELAY;
+                    if process.is_none() && restart_at.is_none() {
+                        state.send_replace(State::Starting);
+                        process = start_process(&spec, &state);
+                        restart_at = process.is_none().then(|| Instant::now() + restart_delay);
+                    }
+                },
+                Some(ProcessCommand::Stop) | None => {
+                    should_run = false;
+                    restart_at = None;
+                    if let Some(running) = process.take() {
+                        state.send_replace(State::Stopping);
+                        terminate(&spec, running.child).await;
+                    }
+                    remove_pid_file(&spec.pid_file);
+                    state.send_replace(State::Stopped);
+
+                    if command.is_none() {
+                        break;
+                    }
+                },
+            },
+
+            status = wait(&mut process) => {
+                let started_at = process.take().map(|p| p.started_at);
+                remove_pid_file(&spec.pid_file);
+                let reason = match status {
+                    Ok(status) => format!("{} exited unexpectedly: {}", spec.name, status),
+                    Err(e) => format!("Failed to wait for {}: {}", spec.name, e),
+                };
+                error!("{}", reason);
+                state.send_replace(State::Failed(reason));
+
+                if started_at.is_some_and(|t| t.elapsed() >= STABLE_RUN_TIME) {
+                    restart_delay = MIN_RESTART_DELAY;
+                }
+
+                if should_run {
+                    info!("Restarting {} in {}s", spec.name, restart_delay.as_secs());
+                    restart_at = Some(Instant::now() + restart_delay);
+                    restart_delay = (restart_delay * 2).min(MAX_RESTART_DELAY);
+                }
+            },
+
+            _ = sleep_until(restart_at) => {
+                restart_at = None;
+                process = start_process(&spec, &state);
+                if process.is_none() {
+                    restart_at = Some(Instant::now() + restart_delay);
+                    restart_delay = (restart_delay * 2).min(MAX_RESTART_DELAY);
+                }
+            },
+        }
+    }
+}
+
+fn start_process(spec: &ProcessSpec, state: &watch::Sender<State>) -> Option<RunningProcess> {
+    match spawn(spec) {
+        Ok(process) => {
+            state.send_replace(State::Running);
+            Some(process)
+        },
+        Err(e) => {
+            let reason = format!("Failed to start {}: {}", spec.name, e);
+            error!("{}", reason);
+            state.send_replace(State::Failed(reason));
+            None
+        },
+    }
+}
+
+fn spawn(spec: &ProcessSpec) -> std::io::Result<RunningProcess> {
+    let mut child = Command::new(&spec.program)
+        .args(&spec.args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    if let Some(pid) = child.id() {
+        info!("Started {} with pid {}", spec.name, pid);
+        if let Err(e) = std::fs::write(&spec.pid_file, pid.to_string()) {
+            warn!("Failed to write {} pid file: {}", spec.name, e);
+        }
+    }
+
+    if let Some(stdout) = child.stdout.take() {
+        tokio::spawn(forward_output(spec.name.clone(), stdout));
+    }
+
+    if let Some(stderr) = child.stderr.take() {
+        tokio::spawn(forward_output(spec.name.clone(), stderr));
+    }
+
+    Ok(RunningProcess { child, started_at: Instant::now() })
+}
+
+async fn forward_output<R>(name: String, output: R) where R: AsyncRead + std::marker::Unpin {
+    let mut lines = BufReader::new(output).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        debug!(process = name; "{}", line);
+    }
+}
+
+async fn terminate(spec: &ProcessSpec, mut child: Child) {
+    if let Some(pid) = child.id() {
+        unsafe { libc::kill(pid as libc::pid_t, libc::SIGINT); }
+    }
+
+    match tokio::time::timeout(STOP_TIMEOUT, child.wait()).await {
+        Ok(Ok(status)) => info!("{} stopped: {}", spec.name, status),
+        Ok(Err(e)) => error!("Failed to wait for {} to stop: {}", spec.name, e),
+        Err(_) => {
+            warn!("{} did not stop in time, killing it", spec.name);
+            child.kill().await.unwrap_or_default();
+        },
+    }
+}
+
+async fn wait(process: &mut Option<RunningProcess>) -> std::io::Result<ExitStatus> {
+    match process {
+        Some(running) => running.child.wait().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+fn remove_pid_file(pid_file: &Path) {
+    if pid_file.exists() {
+        std::fs::remove_file(pid_file).unwrap_or_default();
+    }
+}
+
+fn remove_stale_pid_file(spec: &ProcessSpec) {
+    let pid = match std::fs::read_to_string(&spec.pid_file) {
+        Ok(contents) => contents.trim().parse::<libc::pid_t>().ok(),
+        Err(_) => return,
+    };
+
+    if let Some(pid) = pid.filter(|pid| *pid > 0) {
+        let command = std::fs::read_to_string(format!("/proc/{}/comm", pid)).unwrap_or_default();
+        let program = Path::new(&spec.program).file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        if command.trim() == program {
+            info!("Stopping {} left over from a previous run (pid {})", spec.name, pid);
+            unsafe { libc::kill(pid, libc::SIGINT); }
+        }
+    }
+
+    info!("Removing stale {} pid file {}", spec.name, spec.pid_file.display());
+    remove_pid_file(&spec.pid_file);
+}
@@ -0,0 +1,134 @@
+use async_trait::async_trait;
+use std::io::{Error, ErrorKind, Result};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use super::{Camera, Parameter, State, Status};
+use crate::settings::CameraSettings;
+
+const DEFAULT_RTSP_PORT: u16 = 554;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct RtspCamera {
+    url: String,
+    port: u16,
+    is_active: AtomicBool,
+}
+
+impl RtspCamera {
+    pub fn new(settings: CameraSettings) -> Result<Self> {
+        let url = url(&settings)?;
+        let (_, port) = host_and_port(&url)?;
+        info!("Passing through RTSP stream {}", without_credentials(&url));
+
+        Ok(RtspCamera { url, port, is_active: AtomicBool::new(false) })
+    }
+
+    pub fn check_available(settings: &CameraSettings) -> Result<()> {
+        let url = url(settings)?;
+        let (host, port) = host_and_port(&url)?;
+        let address = (host.as_str(), port).to_socket_addrs()?
+            .next()
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("Failed to resolve {}", host)))?;
+
+        TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)
+            .map(|_| ())
+            .map_err(|e| Error::new(e.kind(), format!("Failed to reach {}: {}", without_credentials(&url), e)))
+    }
+}
+
+#[async_trait]
+impl Camera for RtspCamera {
+    async fn start(&self) -> Result<()> {
+        self.is_active.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    async fn stop(&self) -> Result<()> {
+        self.is_active.store(false, Ordering::Release);
+        Ok(())
+    }
+
+    fn status(&self) -> Status {
+        Status {
+            state: if self.is_active.load(Ordering::Acquire) { State::Running } else { State::Stopped },
+            width: 0,
+            height: 0,
+            framerate: 0,
+            capabilities: 0,
+        }
+    }
+
+    fn port(&self) -> u16 {
+        self.port
+    }
+
+    fn stream_url(&self) -> Option<String> {
+        Some(without_credentials(&self.url))
+    }
+
+    async fn get_parameter(&self, parameter: Parameter) -> Result<i32> {
+        Err(unsupported(parameter))
+    }
+
+    async fn set_parameter(&self, parameter: Parameter, _value: i32) -> Result<()> {
+        Err(unsupported(parameter))
+    }
+
+    async fn is_detection_enabled(&self) -> Result<bool> {
+        Ok(false)
+    }
+
+    async fn set_detection_enabled(&self, _enabled: bool) -> Result<()> {
+        Err(Error::new(ErrorKind::Unsupported, "RTSP camera has no motion detection"))
+    }
+
+    async fn snapshot(&self) -> Result<Vec<u8>> {
+        Err(Error::new(ErrorKind::Unsupported, "RTSP camera does not support snapshots"))
+    }
+
+    fn recordings_dir(&self) -> Option<PathBuf> {
+        None
+    }
+}
+
+fn url(settings: &CameraSettings) -> Result<String> {
+    settings.rtsp_url.as_ref()
+        .filter(|url| !url.trim().is_empty())
+        .cloned()
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "rtsp_url is not set"))
+}
+
+fn host_and_port(url: &str) -> Result<(String, u16)> {
+    let invalid = || Error::new(ErrorKind::InvalidInput, format!("Invalid RTSP url {}", without_credentials(url)));
+
+    let authority = url.strip_prefix("rtsp://").ok_or_else(invalid)?
+        .split('/').next().unwrap_or_default();
+    let authority = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+
+    let (host, port) = match authority.rsplit_once(':').filter(|(_, port)| !port.ends_with(']')) {
+        Some((host, port)) => (host, port.parse::<u16>().map_err(|_| invalid())?),
+        None => (authority, DEFAULT_RTSP_PORT),
+    };
+
+    if host.is_empty() {
+        return Err(invalid());
+    }
+
+    Ok((host.trim_start_matches('[').trim_end_matches(']').to_owned(), port))
+}
+
+fn without_credentials(url: &str) -> String {
+    let (scheme, rest) = url.split_at(url.find("://").map_or(0, |index| index + 3));
+    let authority_end = rest.find('/').unwrap_or(rest.len());
+    match rest[..authority_end].rsplit_once('@') {
+        Some((_, host)) => format!("{}{}{}", scheme, host, &rest[authority_end..]),
+        None => url.to_owned(),
+    }
+}
+
+fn unsupported(parameter: Parameter) -> Error {
+    Error::new(ErrorKind::Unsupported, format!("RTSP camera does not support {:?}", parameter))
+}
@@ -0,0 +1,112 @@
+use std::io::{Error, Result};
+
+use jpeg_encoder::{ColorType, Encoder};
+
+const BAR_COLORS: [[u8; 3]; 8] = [
+    [192, 192, 192],
+    [192, 192, 0],
+    [0, 192, 192],
+    [0, 192, 0],
+    [192, 0, 192],
+    [192, 0, 0],
+    [0, 0, 192],
+    [16, 16, 16],
+];
+
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+const GLYPHS: [(char, [u8; GLYPH_HEIGHT]); 19] = [
+    ('0', [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110]),
+    ('1', [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('2', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111]),
+    ('3', [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110]),
+    ('4', [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010]),
+    ('5', [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110]),
+    ('6', [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110]),
+    ('7', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000]),
+    ('8', [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110]),
+    ('9', [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100]),
+    (':', [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000]),
+    ('-', [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000]),
+    ('.', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100]),
+    ('P', [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('A', [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('N', [0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001, 0b10001]),
+    ('T', [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100]),
+    ('I', [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('L', [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111]),
+];
+
+pub struct TestPattern {
+    width: u16,
+    height: u16,
+    pixels: Vec<u8>,
+}
+
+impl TestPattern {
+    pub fn new(width: u16, height: u16) -> Self {
+        let pixels = vec![0_u8; width as usize * height as usize * 3];
+        TestPattern { width, height, pixels }
+    }
+
+    pub fn draw_bars(&mut self, offset: usize) {
+        let width = self.width as usize;
+        let row_length = width * 3;
+        for (column, pixel) in self.pixels[..row_length].chunks_exact_mut(3).enumerate() {
+            let x = (column + offset) % width;
+            pixel.copy_from_slice(&BAR_COLORS[x * BAR_COLORS.len() / width]);
+        }
+
+        for row in 1..self.height as usize {
+            self.pixels.copy_within(..row_length, row * row_length);
+        }
+    }
+
+    pub fn draw_text(&mut self, x: usize, y: usize, scale: usize, text: &str) {
+        let advance = (GLYPH_WIDTH + 1) * scale;
+        let box_width = text.chars().count() * advance + scale;
+        let box_height = (GLYPH_HEIGHT + 2) * scale;
+        self.fill_rect(x, y, box_width, box_height, [0, 0, 0]);
+
+        for (index, character) in text.chars().enumerate() {
+            let rows = match GLYPHS.iter().find(|(c, _)| *c == character.to_ascii_uppercase()) {
+                Some((_, rows)) => rows,
+                None => continue,
+            };
+
+            let glyph_x = x + scale + index * advance;
+            let glyph_y = y + scale;
+            for (row, bits) in rows.iter().enumerate() {
+                for column in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0 {
+                        self.fill_rect(glyph_x + column * scale, glyph_y + row * scale, scale, scale, [255, 255, 255]);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: [u8; 3]) {
+        let image_width = self.width as usize;
+        let image_height = self.height as usize;
+        for row in y.min(image_height)..(y + height).min(image_height) {
+            for column in x.min(image_width)..(x + width).min(image_width) {
+                let offset = (row * image_width + column) * 3;
+                self.pixels[offset..offset + 3].copy_from_slice(&color);
+            }
+        }
+    }
+
+    pub fn encode(&self, quality: u8) -> Result<Vec<u8>> {
+        let mut jpeg = Vec::new();
+        Encoder::new(&mut jpeg, quality)
+            .encode(&self.pixels, self.width, self.height, ColorType::Rgb)
+            .map_err(|e| Error::other(e.to_string()))?;
+
+        Ok(jpeg)
+    }
+}
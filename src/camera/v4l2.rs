@@ -0,0 +1,471 @@
+use std::ffi::CString;
+use std::io::{Error, ErrorKind, Result};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const V4L2_BUF_TYPE_VIDEO_CAPTURE: u32 = 1;
+const V4L2_MEMORY_MMAP: u32 = 1;
+const V4L2_FIELD_ANY: u32 = 0;
+const V4L2_CAP_VIDEO_CAPTURE: u32 = 0x1;
+const V4L2_CAP_STREAMING: u32 = 0x0400_0000;
+const V4L2_PIX_FMT_MJPEG: u32 = fourcc(b"MJPG");
+
+const BUFFER_COUNT: u32 = 4;
+const POLL_TIMEOUT: i32 = 1000;
+
+#[repr(C)]
+#[derive(Default)]
+struct Capability {
+    driver: [u8; 16],
+    card: [u8; 32],
+    bus_info: [u8; 32],
+    version: u32,
+    capabilities: u32,
+    device_caps: u32,
+    reserved: [u32; 3],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct PixFormat {
+    width: u32,
+    height: u32,
+    pixelformat: u32,
+    field: u32,
+    bytesperline: u32,
+    sizeimage: u32,
+    colorspace: u32,
+    private: u32,
+    flags: u32,
+    ycbcr_enc: u32,
+    quantization: u32,
+    xfer_func: u32,
+}
+
+#[repr(C)]
+union FormatData {
+    pix: PixFormat,
+    raw_data: [u8; 200],
+    _align: [libc::c_ulong; 0],
+}
+
+#[repr(C)]
+struct Format {
+    buffer_type: u32,
+    fmt: FormatData,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct Fraction {
+    numerator: u32,
+    denominator: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct CaptureParameters {
+    capability: u32,
+    capturemode: u32,
+    timeperframe: Fraction,
+    extendedmode: u32,
+    readbuffers: u32,
+    reserved: [u32; 4],
+}
+
+#[repr(C)]
+union StreamParametersData {
+    capture: CaptureParameters,
+    raw_data: [u8; 200],
+}
+
+#[repr(C)]
+struct StreamParameters {
+    buffer_type: u32,
+    parm: StreamParametersData,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct RequestBuffers {
+    count: u32,
+    buffer_type: u32,
+    memory: u32,
+    capabilities: u32,
+    flags: u8,
+    reserved: [u8; 3],
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Timecode {
+    timecode_type: u32,
+    flags: u32,
+    frames: u8,
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    userbits: [u8; 4],
+}
+
+#[repr(C)]
+union BufferLocation {
+    offset: u32,
+    userptr: libc::c_ulong,
+    fd: i32,
+}
+
+#[repr(C)]
+struct Buffer {
+    index: u32,
+    buffer_type: u32,
+    bytesused: u32,
+    flags: u32,
+    field: u32,
+    timestamp: libc::timeval,
+    timecode: Timecode,
+    sequence: u32,
+    memory: u32,
+    m: BufferLocation,
+    length: u32,
+    reserved2: u32,
+    request_fd: i32,
+}
+
+const fn fourcc(code: &[u8; 4]) -> u32 {
+    code[0] as u32 | (code[1] as u32) << 8 | (code[2] as u32) << 16 | (code[3] as u32) << 24
+}
+
+const fn ioc(direction: u32, number: u32, size: usize) -> u32 {
+    direction << 30 | (size as u32) << 16 | (b'V' as u32) << 8 | number
+}
+
+const IOC_WRITE: u32 = 1;
+const IOC_READ: u32 = 2;
+
+const VIDIOC_QUERYCAP: u32 = ioc(IOC_READ, 0, std::mem::size_of::<Capability>());
+const VIDIOC_S_FMT: u32 = ioc(IOC_READ | IOC_WRITE, 5, std::mem::size_of::<Format>());
+const VIDIOC_REQBUFS: u32 = ioc(IOC_READ | IOC_WRITE, 8, std::mem::size_of::<RequestBuffers>());
+const VIDIOC_QUERYBUF: u32 = ioc(IOC_READ | IOC_WRITE, 9, std::mem::size_of::<Buffer>());
+const VIDIOC_QBUF: u32 = ioc(IOC_READ | IOC_WRITE, 15, std::mem::size_of::<Buffer>());
+const VIDIOC_DQBUF: u32 = ioc(IOC_READ | IOC_WRITE, 17, std::mem::size_of::<Buffer>());
+const VIDIOC_STREAMON: u32 = ioc(IOC_WRITE, 18, std::mem::size_of::<i32>());
+const VIDIOC_STREAMOFF: u32 = ioc(IOC_WRITE, 19, std::mem::size_of::<i32>());
+const VIDIOC_S_PARM: u32 = ioc(IOC_READ | IOC_WRITE, 22, std::mem::size_of::<StreamParameters>());
+
+pub trait FrameSource: Send {
+    fn next_frame(&mut self) -> Result<Option<Vec<u8>>>;
+}
+
+pub fn open_frame_source(path: &Path, width: u32, height: u32, framerate: u32) -> Result<Box<dyn FrameSource>> {
+    let metadata = std::fs::metadata(path)?;
+    if metadata.is_dir() || metadata.is_file() {
+        Ok(Box::new(FileFrameSource::new(path, framerate)?))
+    } else {
+        Ok(Box::new(Device::open(path, width, height, framerate)?))
+    }
+}
+
+struct MappedBuffer {
+    pointer: *mut libc::c_void,
+    length: usize,
+}
+
+pub struct Device {
+    fd: libc::c_int,
+    buffers: Vec<MappedBuffer>,
+}
+
+unsafe impl Send for Device {}
+
+impl Device {
+    pub fn open(path: &Path, width: u32, height: u32, framerate: u32) -> Result<Self> {
+        let path_string = CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+        let fd = unsafe { libc::open(path_string.as_ptr(), libc::O_RDWR | libc::O_NONBLOCK) };
+        if fd < 0 {
+            return Err(Error::last_os_error());
+        }
+
+        let mut device = Device { fd, buffers: Vec::new() };
+        device.check_capabilities()?;
+        device.set_format(width, height)?;
+        device.set_framerate(framerate);
+        device.map_buffers()?;
+        device.start_streaming()?;
+
+        Ok(device)
+    }
+
+    fn check_capabilities(&mut self) -> Result<()> {
+        let mut capability = Capability::default();
+        self.ioctl(VIDIOC_QUERYCAP, &mut capability)?;
+
+        let capabilities = if capability.device_caps != 0 { capability.device_caps } else { capability.capabilities };
+        if capabilities & V4L2_CAP_VIDEO_CAPTURE == 0 || capabilities & V4L2_CAP_STREAMING == 0 {
+            return Err(Error::new(ErrorKind::Unsupported, "Device does not support streaming video capture"));
+        }
+
+        let card = String::from_utf8_lossy(&capability.card);
+        info!("Opened V4L2 device {}", card.trim_end_matches('\0'));
+        Ok(())
+    }
+
+    fn set_format(&mut self, width: u32, height: u32) -> Result<()> {
+        let mut format = Format {
+            buffer_type: V4L2_BUF_TYPE_VIDEO_CAPTURE,
+            fmt: FormatData { raw_data: [0; 200] },
+        };
+        format.fmt.pix = PixFormat {
+            width,
+            height,
+            pixelformat: V4L2_PIX_FMT_MJPEG,
+            field: V4L2_FIELD_ANY,
+            ..Default::default()
+        };
+        self.ioctl(VIDIOC_S_FMT, &mut format)?;
+
+        let pix = unsafe { format.fmt.pix };
+        if pix.pixelformat != V4L2_PIX_FMT_MJPEG {
+            return Err(Error::new(ErrorKind::Unsupported, "Device does not support MJPEG capture"));
+        }
+
+        info!("Capturing {}x{} MJPEG", pix.width, pix.height);
+        Ok(())
+    }
+
+    fn set_framerate(&mut self, framerate: u32) {
+        let mut parameters = StreamParameters {
+            buffer_type: V4L2_BUF_TYPE_VIDEO_CAPTURE,
+            parm: StreamParametersData { raw_data: [0; 200] },
+        };
+        parameters.parm.capture = CaptureParameters {
+            timeperframe: Fraction { numerator: 1, denominator: framerate.max(1) },
+            ..Default::default()
+        };
+
+        if let Err(e) = self.ioctl(VIDIOC_S_PARM, &mut parameters) {
+            warn!("Failed to set capture framerate: {}", e);
+        }
+    }
+
+    fn map_buffers(&mut self) -> Result<()> {
+        let mut request = RequestBuffers {
+            count: BUFFER_COUNT,
+            buffer_type: V4L2_BUF_TYPE_VIDEO_CAPTURE,
+            memory: V4L2_MEMORY_MMAP,
+            ..Default::default()
+        };
+        self.ioctl(VIDIOC_REQBUFS, &mut request)?;
+
+        for index in 0..request.count {
+            let mut buffer = new_buffer(index);
+            self.ioctl(VIDIOC_QUERYBUF, &mut buffer)?;
+
+            let length = buffer.length as usize;
+            let pointer = unsafe {
+                libc::mmap(
+                    std::ptr::null_mut(),
+                    length,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_SHARED,
+                    self.fd,
+                    buffer.m.offset as libc::off_t)
+            };
+            if pointer == libc::MAP_FAILED {
+                return Err(Error::last_os_error());
+            }
+
+            self.buffers.push(MappedBuffer { pointer, length });
+            self.ioctl(VIDIOC_QBUF, &mut buffer)?;
+        }
+
+        Ok(())
+    }
+
+    fn start_streaming(&mut self) -> Result<()> {
+        let mut buffer_type = V4L2_BUF_TYPE_VIDEO_CAPTURE as i32;
+        self.ioctl(VIDIOC_STREAMON, &mut buffer_type)
+    }
+
+    fn ioctl<T>(&self, request: u32, argument: &mut T) -> Result<()> {
+        loop {
+            let result = unsafe { libc::ioctl(self.fd, request as _, argument as *mut T) };
+            if result >= 0 {
+                return Ok(());
+            }
+
+            let error = Error::last_os_error();
+            if error.kind() != ErrorKind::Interrupted {
+                return Err(error);
+            }
+        }
+    }
+}
+
+impl FrameSource for Device {
+    fn next_frame(&mut self) -> Result<Option<Vec<u8>>> {
+        let mut poll_fd = libc::pollfd { fd: self.fd, events: libc::POLLIN, revents: 0 };
+        let ready = unsafe { libc::poll(&mut poll_fd, 1, POLL_TIMEOUT) };
+        if ready < 0 {
+            let error = Error::last_os_error();
+            return if error.kind() == ErrorKind::Interrupted { Ok(None) } else { Err(error) };
+        } else if ready == 0 {
+            return Ok(None);
+        }
+
+        let mut buffer = new_buffer(0);
+        self.ioctl(VIDIOC_DQBUF, &mut buffer)?;
+
+        let mapped = &self.buffers[buffer.index as usize];
+        let length = (buffer.bytesused as usize).min(mapped.length);
+        let frame = unsafe { std::slice::from_raw_parts(mapped.pointer as *const u8, length) }.to_vec();
+
+        self.ioctl(VIDIOC_QBUF, &mut buffer)?;
+        Ok(Some(frame))
+    }
+}
+
+impl Drop for Device {
+    fn drop(&mut self) {
+        let mut buffer_type = V4L2_BUF_TYPE_VIDEO_CAPTURE as i32;
+        self.ioctl(VIDIOC_STREAMOFF, &mut buffer_type).unwrap_or_default();
+
+        for buffer in &self.buffers {
+            unsafe { libc::munmap(buffer.pointer, buffer.length); }
+        }
+
+        unsafe { libc::close(self.fd); }
+    }
+}
+
+fn new_buffer(index: u32) -> Buffer {
+    Buffer {
+        index,
+        buffer_type: V4L2_BUF_TYPE_VIDEO_CAPTURE,
+        bytesused: 0,
+        flags: 0,
+        field: 0,
+        timestamp: libc::timeval { tv_sec: 0, tv_usec: 0 },
+        timecode: Timecode::default(),
+        sequence: 0,
+        memory: V4L2_MEMORY_MMAP,
+        m: BufferLocation { offset: 0 },
+        length: 0,
+        reserved2: 0,
+        request_fd: 0,
+    }
+}
+
+pub struct FileFrameSource {
+    frames: Vec<PathBuf>,
+    mjpeg: Vec<u8>,
+    position: usize,
+    interval: Duration,
+    last_frame: Option<Instant>,
+}
+
+impl FileFrameSource {
+    pub fn new(path: &Path, framerate: u32) -> Result<Self> {
+        let interval = Duration::from_secs(1) / framerate.max(1);
+        let mut source = FileFrameSource { frames: Vec::new(), mjpeg: Vec::new(), position: 0, interval, last_frame: None };
+
+        if path.is_dir() {
+            source.frames = std::fs::read_dir(path)?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| p.extension().is_some_and(|e| e == "jpg" || e == "jpeg"))
+                .collect();
+            source.frames.sort();
+        } else {
+            source.mjpeg = std::fs::read(path)?;
+        }
+
+        if source.frames.is_empty() && find_frame(&source.mjpeg, 0).is_none() {
+            return Err(Error::new(ErrorKind::InvalidData, format!("No JPEG frames found in {}", path.display())));
+        }
+
+        info!("Reading frames from {}", path.display());
+        Ok(source)
+    }
+}
+
+impl FrameSource for FileFrameSource {
+    fn next_frame(&mut self) -> Result<Option<Vec<u8>>> {
+        if let Some(last_frame) = self.last_frame {
+            std::thread::sleep(self.interval.saturating_sub(last_frame.elapsed()));
+        }
+        self.last_frame = Some(Instant::now());
+
+        if !self.frames.is_empty() {
+            let frame = std::fs::read(&self.frames[self.position % self.frames.len()])?;
+            self.position = (self.position + 1) % self.frames.len();
+            return Ok(Some(frame));
+        }
+
+        let (start, end) = match find_frame(&self.mjpeg, self.position) {
+            Some(range) => range,
+            None => find_frame(&self.mjpeg, 0).ok_or_else(|| Error::new(ErrorKind::InvalidData, "No JPEG frames"))?,
+        };
+        self.position = end;
+        Ok(Some(self.mjpeg[start..end].to_vec()))
+    }
+}
+
+fn find_frame(data: &[u8], from: usize) -> Option<(usize, usize)> {
+    let start = from + data.get(from..)?.windows(2).position(|w| w == [0xFF, 0xD8])?;
+    let end = start + 2 + data[start + 2..].windows(2).position(|w| w == [0xFF, 0xD9])? + 2;
+    Some((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jpeg(payload: &[u8]) -> Vec<u8> {
+        [&[0xFF, 0xD8][..], payload, &[0xFF, 0xD9]].concat()
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("eye-v4l2-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn file_source_yields_frames_in_order_and_loops() {
+        let frames = [jpeg(b"first"), jpeg(b"second"), jpeg(b"third")];
+        let path = temp_path("frames.mjpeg");
+        std::fs::write(&path, [&b"--boundary\r\n"[..], &frames[0], b"\r\n--boundary\r\n", &frames[1], &frames[2]].concat()).unwrap();
+
+        let mut source = FileFrameSource::new(&path, 1000).unwrap();
+        let received = (0..5).map(|_| source.next_frame().unwrap().unwrap()).collect::<Vec<_>>();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(received, [&frames[..], &frames[..2]].concat());
+    }
+
+    #[test]
+    fn file_source_reads_a_directory_of_jpegs() {
+        let dir = temp_path("frames");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("2.jpg"), jpeg(b"second")).unwrap();
+        std::fs::write(dir.join("1.jpg"), jpeg(b"first")).unwrap();
+        std::fs::write(dir.join("notes.txt"), b"not a frame").unwrap();
+
+        let mut source = FileFrameSource::new(&dir, 1000).unwrap();
+        let received = (0..3).map(|_| source.next_frame().unwrap().unwrap()).collect::<Vec<_>>();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(received, [jpeg(b"first"), jpeg(b"second"), jpeg(b"first")]);
+    }
+
+    #[test]
+    fn file_source_rejects_files_without_frames() {
+        let path = temp_path("empty.mjpeg");
+        std::fs::write(&path, b"no frames here").unwrap();
+
+        let result = FileFrameSource::new(&path, 1000);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(result.err().map(|e| e.kind()), Some(ErrorKind::InvalidData));
+    }
+}
@@ -0,0 +1,234 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use std::io::{Error, ErrorKind, Result};
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+use std::thread::JoinHandle;
+use tokio::sync::broadcast;
+
+use super::{capabilities, Camera, Parameter, State, Status};
+use super::v4l2;
+use crate::mjpeg;
+use crate::settings::CameraSettings;
+
+const FRAME_BUFFER_SIZE: usize = 4;
+
+pub struct V4l2Camera {
+    device: PathBuf,
+    width: u32,
+    height: u32,
+    framerate: AtomicI32,
+    port: u16,
+    frames: broadcast::Sender<Bytes>,
+    latest_frame: Arc<Mutex<Option<Bytes>>>,
+    state: Arc<Mutex<State>>,
+    generation: Arc<AtomicUsize>,
+    capture_thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl V4l2Camera {
+    pub fn new(settings: CameraSettings) -> Result<Self> {
+        let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, settings.stream_port))?;
+        listener.set_nonblocking(true)?;
+        let listener = tokio::net::TcpListener::from_std(listener)?;
+
+        let (frames, _) = broadcast::channel(FRAME_BUFFER_SIZE);
+        tokio::spawn(mjpeg::serve(listener, frames.clone()));
+        info!("Serving {} on port {}", settings.video_device.display(), settings.stream_port);
+
+        Ok(V4l2Camera {
+            device: settings.video_device,
+            width: settings.width,
+            height: settings.height,
+            framerate: AtomicI32::new(settings.framerate as i32),
+            port: settings.stream_port,
+            frames,
+            latest_frame: Arc::new(Mutex::new(None)),
+            state: Arc::new(Mutex::new(State::Stopped)),
+            generation: Arc::new(AtomicUsize::new(0)),
+            capture_thread: Mutex::new(None),
+        })
+    }
+
+    pub fn check_available(settings: &CameraSettings) -> Result<()> {
+        if settings.video_device.exists() {
+            Ok(())
+        } else {
+            Err(Error::new(ErrorKind::NotFound, format!("{} does not exist", settings.video_device.display())))
+        }
+    }
+}
+
+#[async_trait]
+impl Camera for V4l2Camera {
+    async fn start(&self) -> Result<()> {
+        if self.state.lock().unwrap().is_active() {
+            return Ok(());
+        }
+
+        // A stopping capture thread still holds the device until it exits
+        let previous = self.capture_thread.lock().unwrap().take();
+        if let Some(previous) = previous {
+            tokio::task::spawn_blocking(move || previous.join()).await.ok();
+        }
+
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.is_active() {
+                return Ok(());
+            }
+            *state = State::Starting;
+        }
+
+        let generation = self.generation.fetch_add(1, Ordering::AcqRel) + 1;
+        let capture = Capture {
+            device: self.device.clone(),
+            width: self.width,
+            height: self.height,
+            framerate: self.framerate.load(Ordering::Acquire) as u32,
+            frames: self.frames.clone(),
+            latest_frame: self.latest_frame.clone(),
+            state: self.state.clone(),
+            generation: self.generation.clone(),
+        };
+        *self.capture_thread.lock().unwrap() = Some(std::thread::spawn(move || capture.run(generation)));
+
+        Ok(())
+    }
+
+    async fn stop(&self) -> Result<()> {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        let mut state = self.state.lock().unwrap();
+        if state.is_active() {
+            *state = State::Stopping;
+        }
+
+        Ok(())
+    }
+
+    fn status(&self) -> Status {
+        Status {
+            state: self.state.lock().unwrap().clone(),
+            width: self.width,
+            height: self.height,
+            framerate: self.framerate.load(Ordering::Acquire).max(0) as u32,
+            capabilities: capabilities::FRAMERATE | capabilities::SNAPSHOT,
+        }
+    }
+
+    fn port(&self) -> u16 {
+        self.port
+    }
+
+    fn stream_url(&self) -> Option<String> {
+        None
+    }
+
+    async fn get_parameter(&self, parameter: Parameter) -> Result<i32> {
+        match parameter {
+            Parameter::Framerate => Ok(self.framerate.load(Ordering::Acquire)),
+            _ => Err(unsupported(parameter)),
+        }
+    }
+
+    async fn set_parameter(&self, parameter: Parameter, value: i32) -> Result<()> {
+        match parameter {
+            Parameter::Framerate if value > 0 => {
+                self.framerate.store(value, Ordering::Release);
+                if self.state.lock().unwrap().is_active() {
+                    info!("New framerate will be applied when the camera restarts");
+                }
+                Ok(())
+            },
+            _ => Err(unsupported(parameter)),
+        }
+    }
+
+    async fn is_detection_enabled(&self) -> Result<bool> {
+        Ok(false)
+    }
+
+    async fn set_detection_enabled(&self, _enabled: bool) -> Result<()> {
+        Err(Error::new(ErrorKind::Unsupported, "V4L2 camera has no motion detection"))
+    }
+
+    async fn snapshot(&self) -> Result<Vec<u8>> {
+        self.latest_frame.lock().unwrap()
+            .as_ref()
+            .map(|frame| frame.to_vec())
+            .ok_or_else(|| Error::new(ErrorKind::NotConnected, "Camera is not capturing"))
+    }
+
+    fn recordings_dir(&self) -> Option<PathBuf> {
+        None
+    }
+}
+
+impl Drop for V4l2Camera {
+    fn drop(&mut self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+    }
+}
+
+struct Capture {
+    device: PathBuf,
+    width: u32,
+    height: u32,
+    framerate: u32,
+    frames: broadcast::Sender<Bytes>,
+    latest_frame: Arc<Mutex<Option<Bytes>>>,
+    state: Arc<Mutex<State>>,
+    generation: Arc<AtomicUsize>,
+}
+
+impl Capture {
+    fn run(self, generation: usize) {
+        let result = v4l2::open_frame_source(&self.device, self.width, self.height, self.framerate)
+            .map_err(|e| format!("Failed to open {}: {}", self.device.display(), e))
+            .and_then(|mut source| {
+                self.update_state(generation, State::Running);
+                while self.is_current(generation) {
+                    match source.next_frame() {
+                        Ok(Some(frame)) => {
+                            let frame = Bytes::from(frame);
+                            *self.latest_frame.lock().unwrap() = Some(frame.clone());
+                            self.frames.send(frame).unwrap_or_default();
+                        },
+                        Ok(None) => continue,
+                        Err(e) => return Err(format!("V4L2 capture failed: {}", e)),
+                    }
+                }
+
+                Ok(())
+            });
+
+        self.latest_frame.lock().unwrap().take();
+        if let Err(reason) = &result {
+            error!("{}", reason);
+        }
+
+        let mut state = self.state.lock().unwrap();
+        if self.is_current(generation) {
+            *state = State::Failed(result.err().unwrap_or_default());
+        } else if *state == State::Stopping {
+            *state = State::Stopped;
+        }
+    }
+
+    fn is_current(&self, generation: usize) -> bool {
+        self.generation.load(Ordering::Acquire) == generation
+    }
+
+    fn update_state(&self, generation: usize, new_state: State) {
+        let mut state = self.state.lock().unwrap();
+        if self.is_current(generation) {
+            *state = new_state;
+        }
+    }
+}
+
+fn unsupported(parameter: Parameter) -> Error {
+    Error::new(ErrorKind::Unsupported, format!("V4L2 camera does not support {:?}", parameter))
+}
@@ -0,0 +1,231 @@
+use std::fmt::Display;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const CAMERA_INCLUDE: &str = "camera";
+const CAMERA_DIR_INCLUDE: &str = "camera_dir";
+
+#[derive(Clone, Debug)]
+enum Line {
+    Text(String),
+    Entry {
+        key: String,
+        value: String,
+        original: Option<String>,
+    },
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    path: Option<PathBuf>,
+    lines: Vec<Line>,
+    cameras: Vec<Config>,
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Config::default()
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let lines = text.lines()
+            .map(|line| {
+                let trimmed = line.trim();
+                if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with(';') {
+                    return Line::Text(line.to_owned());
+                }
+
+                let (key, value) = trimmed.split_once(char::is_whitespace).unwrap_or((trimmed, ""));
+                Line::Entry {
+                    key: key.to_owned(),
+                    value: value.trim().to_owned(),
+                    original: Some(line.to_owned()),
+                }
+            })
+            .collect();
+
+        Config { path: None, lines, cameras: Vec::new() }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let mut config = Self::parse(&text);
+        config.path = Some(path.to_owned());
+
+        let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let mut camera_files = config.values(CAMERA_INCLUDE)
+            .map(|include| base_dir.join(include))
+            .collect::<Vec<_>>();
+
+        for dir in config.values(CAMERA_DIR_INCLUDE) {
+            let mut files = std::fs::read_dir(base_dir.join(dir))?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| p.extension().is_some_and(|e| e == "conf"))
+                .collect::<Vec<_>>();
+            files.sort();
+            camera_files.extend(files);
+        }
+
+        config.cameras = camera_files.iter()
+            .map(|file| Self::load(file))
+            .collect::<Result<_>>()?;
+
+        Ok(config)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.to_string())
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn cameras(&self) -> &[Config] {
+        &self.cameras
+    }
+
+    pub fn cameras_mut(&mut self) -> &mut [Config] {
+        &mut self.cameras
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries().filter(|(k, _)| *k == key).map(|(_, v)| v).last()
+    }
+
+    pub fn values<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries().filter(move |(k, _)| *k == key).map(|(_, v)| v)
+    }
+
+    fn entries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Entry { key, value, .. } => Some((key.as_str(), value.as_str())),
+            Line::Text(_) => None,
+        })
+    }
+
+    pub fn value<T>(&self, key: &str) -> Result<T> where T: FromStr, T::Err: Display {
+        let value = self.get(key)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("Failed to find {} setting in {}", key, self.name())))?;
+
+        value.parse::<T>().map_err(|e|
+            Error::new(ErrorKind::InvalidData, format!("Failed to parse {} from {}.\n{}", key, self.name(), e))
+        )
+    }
+
+    pub fn value_or<T>(&self, key: &str, default: T) -> Result<T> where T: FromStr, T::Err: Display {
+        if self.contains(key) {
+            self.value(key)
+        } else {
+            Ok(default)
+        }
+    }
+
+    pub fn set<V>(&mut self, key: &str, value: V) where V: ToString {
+        let new_value = value.to_string();
+        let existing = self.lines.iter_mut().rev().find_map(|line| match line {
+            Line::Entry { key: k, value, original } if k == key => Some((value, original)),
+            _ => None,
+        });
+
+        match existing {
+            Some((value, original)) => {
+                if *value != new_value {
+                    *value = new_value;
+                    *original = None;
+                }
+            },
+            None => self.lines.push(Line::Entry { key: key.to_owned(), value: new_value, original: None }),
+        }
+    }
+
+    pub fn set_values<V>(&mut self, key: &str, values: &[V]) where V: ToString {
+        let position = self.lines.iter()
+            .position(|line| matches!(line, Line::Entry { key: k, .. } if k == key))
+            .unwrap_or(self.lines.len());
+
+        self.lines.retain(|line| !matches!(line, Line::Entry { key: k, .. } if k == key));
+        let entries = values.iter().map(|value| Line::Entry { key: key.to_owned(), value: value.to_string(), original: None });
+        self.lines.splice(position.min(self.lines.len())..position.min(self.lines.len()), entries);
+    }
+
+    pub fn add_comment(&mut self, comment: &str) {
+        self.lines.push(Line::Text(format!("# {}", comment)));
+    }
+
+    fn name(&self) -> String {
+        self.path.as_ref().map_or_else(|| String::from("config"), |p| p.display().to_string())
+    }
+}
+
+impl Display for Config {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for line in &self.lines {
+            match line {
+                Line::Text(text) => writeln!(formatter, "{}", text)?,
+                Line::Entry { original: Some(original), .. } => writeln!(formatter, "{}", original)?,
+                Line::Entry { key, value, .. } if value.is_empty() => writeln!(formatter, "{}", key)?,
+                Line::Entry { key, value, .. } => writeln!(formatter, "{} {}", key, value)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "\
+# Camera settings
+width   640
+; height is derived from the aspect ratio
+height 480
+
+servo_preset door 0 90 90
+# the window preset points outside
+servo_preset window 0 30 120
+flag
+";
+
+    #[test]
+    fn unchanged_round_trip_is_identical() {
+        assert_eq!(Config::parse(TEXT).to_string(), TEXT);
+    }
+
+    #[test]
+    fn set_keeps_surrounding_comments() {
+        let mut config = Config::parse(TEXT);
+        config.set("height", 720);
+        config.set("width", 640);
+
+        assert_eq!(config.to_string(), TEXT.replace("height 480", "height 720"));
+        assert_eq!(config.value::<u32>("height").unwrap(), 720);
+    }
+
+    #[test]
+    fn set_appends_missing_keys() {
+        let mut config = Config::parse(TEXT);
+        config.set("framerate", 15);
+
+        assert_eq!(config.to_string(), format!("{}framerate 15\n", TEXT));
+    }
+
+    #[test]
+    fn set_values_replaces_multi_valued_keys_in_place() {
+        let mut config = Config::parse(TEXT);
+        config.set_values("servo_preset", &["hall 1 10 20", "garden 1 170 60"]);
+
+        let expected = TEXT
+            .replace("servo_preset door 0 90 90\n", "servo_preset hall 1 10 20\nservo_preset garden 1 170 60\n")
+            .replace("servo_preset window 0 30 120\n", "");
+        assert_eq!(config.to_string(), expected);
+        assert_eq!(config.values("servo_preset").collect::<Vec<_>>(), ["hall 1 10 20", "garden 1 170 60"]);
+    }
+}
@@ -0,0 +1,130 @@
+use std::net::Ipv4Addr;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::process::Command;
+
+use crate::fs::Fs;
+use crate::servo;
+use crate::settings::Settings;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct Check {
+    pub name: String,
+    pub success: bool,
+    pub details: String,
+}
+
+pub struct StreamPort {
+    pub camera_id: u32,
+    pub name: &'static str,
+    pub port: u16,
+}
+
+pub async fn run(fs: &Fs, settings: &Settings, uses_motion: bool, stream_ports: &[StreamPort]) -> Vec<Check> {
+    let mut checks = Vec::new();
+    check_servo_boards(settings, &mut checks);
+    checks.push(check_motion(uses_motion).await);
+    check_paths(fs, &mut checks);
+    for stream_port in stream_ports {
+        checks.push(check_port(stream_port).await);
+    }
+    check_interfaces(&mut checks);
+    checks
+}
+
+fn check_servo_boards(settings: &Settings, checks: &mut Vec<Check>) {
+    let servo_settings = match settings.servo() {
+        Ok(servo_settings) => servo_settings,
+        Err(e) => return checks.push(Check::new("servo settings", Err(e.to_string()))),
+    };
+
+    for board in &servo_settings.boards {
+        let target = format!("PCA9685 at {:#04x} on {}", board.address, servo_settings.i2c_device.display());
+        match servo::probe_board(&servo_settings.i2c_device, board.address) {
+            Ok(probe) => {
+                checks.push(Check::new(
+                    &format!("i2c board {}", board.id),
+                    Ok(format!("{} responded, MODE1 {:#04x}", target, probe.mode))));
+                let prescale = format!("prescale {}, expected {}", probe.prescale, probe.expected_prescale);
+                checks.push(Check::new(
+                    &format!("pwm prescale board {}", board.id),
+                    if probe.prescale == probe.expected_prescale { Ok(prescale) } else { Err(prescale) }));
+            },
+            Err(e) => checks.push(Check::new(&format!("i2c board {}", board.id), Err(format!("{}: {}", target, e)))),
+        }
+    }
+}
+
+async fn check_motion(uses_motion: bool) -> Check {
+    let result = match Command::new("motion").arg("-h").output().await {
+        Ok(output) => {
+            let text = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+            let version = text.lines()
+                .find(|line| line.contains("Version"))
+                .or_else(|| text.lines().find(|line| !line.trim().is_empty()))
+                .unwrap_or_default();
+            Ok(version.trim().to_owned())
+        },
+        Err(e) if uses_motion => Err(format!("Failed to run motion: {}", e)),
+        Err(e) => Ok(format!("Not used by any camera, failed to run motion: {}", e)),
+    };
+
+    Check::new("motion version", result)
+}
+
+fn check_paths(fs: &Fs, checks: &mut Vec<Check>) {
+    let paths = [
+        ("settings file", fs.settings_file()),
+        ("event socket", fs.event_socket_file()),
+        ("event history", fs.history_file()),
+        ("log file", fs.log_file()),
+    ];
+
+    for (name, path) in paths {
+        let result = path
+            .map(|path| format!("{}{}", path.display(), if path.exists() { "" } else { " (missing)" }))
+            .map_err(|e| e.to_string());
+        checks.push(Check::new(name, result));
+    }
+}
+
+async fn check_port(stream_port: &StreamPort) -> Check {
+    let name = format!("camera {} {} port {}", stream_port.camera_id, stream_port.name, stream_port.port);
+    let result = match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect((Ipv4Addr::LOCALHOST, stream_port.port))).await {
+        Ok(Ok(_)) => Ok(String::from("reachable")),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err(String::from("timed out")),
+    };
+
+    Check::new(&name, result)
+}
+
+fn check_interfaces(checks: &mut Vec<Check>) {
+    let interfaces = pnet::datalink::interfaces();
+    for interface in &interfaces {
+        let mut details = String::from(if interface.is_up() { "up" } else { "down" });
+        for ip in &interface.ips {
+            details.push_str(&format!(", {}", ip));
+        }
+        checks.push(Check::new(&format!("interface {}", interface.name), Ok(details)));
+    }
+
+    let is_connected = interfaces.iter().any(|i| i.is_up() && !i.is_loopback() && !i.ips.is_empty());
+    let result = match is_connected {
+        true => Ok(format!("Streams are served at {}", crate::get_current_ip_address())),
+        false => Err(String::from("No network interface is up with an address")),
+    };
+    checks.push(Check::new("network", result));
+}
+
+impl Check {
+    fn new(name: &str, result: Result<String, String>) -> Self {
+        let (success, details) = match result {
+            Ok(details) => (true, details),
+            Err(details) => (false, details),
+        };
+
+        Check { name: name.to_owned(), success, details }
+    }
+}
@@ -0,0 +1,68 @@
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+use prost::Message;
+use tokio::io::AsyncReadExt;
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::mpsc::{self, Receiver, Sender};
+
+use crate::networking::{self, MessageType};
+use crate::server::messages::MotionEvent;
+
+const EVENT_BUFFER_SIZE: usize = 32;
+const MAX_EVENT_SIZE: usize = 4096;
+
+pub fn listen(path: &Path) -> Result<Receiver<MotionEvent>> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+        _ => {},
+    }
+
+    let listener = UnixListener::bind(path)?;
+    info!("Listening for motion events on {}", path.display());
+
+    let (sender, receiver) = mpsc::channel(EVENT_BUFFER_SIZE);
+    tokio::spawn(accept_routine(listener, sender));
+    Ok(receiver)
+}
+
+async fn accept_routine(listener: UnixListener, sender: Sender<MotionEvent>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = read_events(stream, &sender).await {
+                        error!("Failed to read motion event: {}", e);
+                    }
+                });
+            },
+            Err(e) => {
+                error!("Failed to accept motion event connection: {}", e);
+                return;
+            },
+        }
+    }
+}
+
+async fn read_events(mut stream: UnixStream, sender: &Sender<MotionEvent>) -> Result<()> {
+    loop {
+        let msg_type = match stream.read_u32_le().await {
+            Ok(msg_type) => msg_type,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        let length = stream.read_u32_le().await? as usize;
+        if networking::msg_type_from_id(msg_type) != Some(MessageType::MotionEvent) || length > MAX_EVENT_SIZE {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Unexpected message {} of {} bytes", msg_type, length)));
+        }
+
+        let mut payload = vec![0_u8; length];
+        stream.read_exact(&mut payload).await?;
+        let event = MotionEvent::decode(payload.as_slice())
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+
+        sender.send(event).await.map_err(|_| Error::from(ErrorKind::BrokenPipe))?;
+    }
+}
@@ -12,11 +12,27 @@ impl Fs {
         Ok(Fs { xdg })
     }
 
-    pub fn camera_pid_file(&self) -> std::io::Result<PathBuf> {
-        self.xdg.place_runtime_file("motion.pid")
+    pub fn camera_pid_file(&self, name: &str) -> std::io::Result<PathBuf> {
+        self.xdg.place_runtime_file(format!("{}.pid", name))
     }
 
-    pub fn camera_config_file(&self) ->std::io::Result<PathBuf> {
-        self.xdg.place_config_file("motion.conf")
+    pub fn camera_config_file(&self, name: &str) -> std::io::Result<PathBuf> {
+        self.xdg.place_runtime_file(format!("{}.conf", name))
+    }
+
+    pub fn event_socket_file(&self) -> std::io::Result<PathBuf> {
+        self.xdg.place_runtime_file("events.sock")
+    }
+
+    pub fn history_file(&self) -> std::io::Result<PathBuf> {
+        self.xdg.place_data_file("events.log")
+    }
+
+    pub fn log_file(&self) -> std::io::Result<PathBuf> {
+        self.xdg.place_state_file("eye.log")
+    }
+
+    pub fn settings_file(&self) -> std::io::Result<PathBuf> {
+        self.xdg.place_config_file("eye.conf")
     }
 }
\ No newline at end of file
@@ -0,0 +1,102 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, ErrorKind, Result};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::rotating_file::RotatingFile;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    ClientConnected,
+    ClientDisconnected,
+    ServoMoved,
+    CameraStateChanged,
+    Motion,
+    AuthFailure,
+}
+
+const KINDS: [(Kind, &str); 6] = [
+    (Kind::ClientConnected, "client_connected"),
+    (Kind::ClientDisconnected, "client_disconnected"),
+    (Kind::ServoMoved, "servo_moved"),
+    (Kind::CameraStateChanged, "camera_state_changed"),
+    (Kind::Motion, "motion"),
+    (Kind::AuthFailure, "auth_failure"),
+];
+
+pub struct Record {
+    pub timestamp: u64,
+    pub kind: Kind,
+    pub source: String,
+    pub details: String,
+}
+
+pub struct History {
+    file: RotatingFile,
+}
+
+impl History {
+    pub fn new(path: PathBuf, max_size: u64, max_files: u32) -> Self {
+        info!("Recording event history to {}", path.display());
+        History { file: RotatingFile::new(path, max_size, max_files) }
+    }
+
+    pub fn record(&self, kind: Kind, source: &str, details: &str) {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let line = format!("{}\t{}\t{}\t{}\n", timestamp, kind.name(), sanitize(source), sanitize(details));
+        if let Err(e) = self.file.append(&line) {
+            error!("Failed to record {} event: {}", kind.name(), e);
+        }
+    }
+
+    pub fn query(&self, from: u64, to: Option<u64>, kinds: &[Kind], limit: usize) -> Result<Vec<Record>> {
+        let mut records = Vec::new();
+        for path in self.file.paths_oldest_first() {
+            let file = match File::open(path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+
+            for line in BufReader::new(file).lines() {
+                let record = match parse_record(&line?) {
+                    Some(record) => record,
+                    None => continue,
+                };
+
+                let is_in_range = record.timestamp >= from && to.is_none_or(|to| record.timestamp <= to);
+                if is_in_range && (kinds.is_empty() || kinds.contains(&record.kind)) {
+                    records.push(record);
+                }
+            }
+        }
+
+        let skipped = records.len().saturating_sub(limit);
+        records.drain(..skipped);
+        Ok(records)
+    }
+}
+
+impl Kind {
+    pub fn name(&self) -> &'static str {
+        KINDS.iter().find(|(kind, _)| kind == self).map_or("", |(_, name)| name)
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        KINDS.iter().find(|(_, n)| *n == name).map(|(kind, _)| *kind)
+    }
+}
+
+fn parse_record(line: &str) -> Option<Record> {
+    let mut fields = line.splitn(4, '\t');
+    Some(Record {
+        timestamp: fields.next()?.parse().ok()?,
+        kind: Kind::from_name(fields.next()?)?,
+        source: fields.next()?.to_owned(),
+        details: fields.next().unwrap_or_default().to_owned(),
+    })
+}
+
+fn sanitize(text: &str) -> String {
+    text.replace(['\t', '\n', '\r'], " ")
+}
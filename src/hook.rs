@@ -0,0 +1,90 @@
+#[allow(dead_code)]
+mod fs;
+#[allow(dead_code)]
+mod networking;
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use messages::{MotionEvent, MotionEventKind};
+use networking::MessageType;
+use tokio::net::UnixStream;
+
+pub mod messages {
+    include!(concat!(env!("OUT_DIR"), "/messages.rs"));
+}
+
+const USAGE: &str = "Usage: eye-hook [--socket <path>] <event_start|event_end|movie_end|picture_save|motion_detected> <camera id> [event=<id>] [file=<path>] [x=<x> y=<y>]";
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match parse_args(std::env::args().skip(1)) {
+        Ok((socket, event)) => match send_event(socket, event).await {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("Failed to deliver motion event: {}", e);
+                ExitCode::FAILURE
+            },
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("{}", USAGE);
+            ExitCode::from(2)
+        },
+    }
+}
+
+async fn send_event(socket: Option<PathBuf>, event: MotionEvent) -> std::io::Result<()> {
+    let socket = match socket {
+        Some(socket) => socket,
+        None => fs::Fs::new().map_err(std::io::Error::other)?.event_socket_file()?,
+    };
+
+    let mut stream = UnixStream::connect(&socket).await?;
+    networking::send_message(&mut stream, MessageType::MotionEvent, event).await
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<(Option<PathBuf>, MotionEvent), String> {
+    let mut socket = None;
+    let mut positional = Vec::new();
+    let mut event = MotionEvent {
+        timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+        ..Default::default()
+    };
+
+    while let Some(arg) = args.next() {
+        if arg == "--socket" {
+            socket = Some(PathBuf::from(args.next().ok_or("Missing socket path")?));
+        } else if let Some(path) = arg.strip_prefix("--socket=") {
+            socket = Some(PathBuf::from(path));
+        } else if let Some(id) = arg.strip_prefix("event=") {
+            event.event_id = id.parse().map_err(|_| format!("Invalid event id {}", id))?;
+        } else if let Some(file) = arg.strip_prefix("file=") {
+            event.file = file.to_owned();
+        } else if let Some(x) = arg.strip_prefix("x=") {
+            event.x = x.parse().map_err(|_| format!("Invalid x coordinate {}", x))?;
+        } else if let Some(y) = arg.strip_prefix("y=") {
+            event.y = y.parse().map_err(|_| format!("Invalid y coordinate {}", y))?;
+        } else {
+            positional.push(arg);
+        }
+    }
+
+    let (kind, camera_id) = match positional.as_slice() {
+        [kind, camera_id] => (kind, camera_id),
+        _ => return Err(String::from("Expected an event kind and a camera id")),
+    };
+
+    event.set_kind(match kind.as_str() {
+        "event_start" => MotionEventKind::EventStart,
+        "event_end" => MotionEventKind::EventEnd,
+        "movie_end" => MotionEventKind::MovieEnd,
+        "picture_save" => MotionEventKind::PictureSave,
+        "motion_detected" => MotionEventKind::MotionDetected,
+        _ => return Err(format!("Unknown event kind {}", kind)),
+    });
+    event.camera_id = camera_id.parse().map_err(|_| format!("Invalid camera id {}", camera_id))?;
+
+    Ok((socket, event))
+}
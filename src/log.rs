@@ -0,0 +1,226 @@
+use std::fmt::{Arguments, Display, Write as _};
+use std::io::{ErrorKind, Result};
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::rotating_file::RotatingFile;
+use crate::settings::LogSettings;
+
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+const SYSLOG_IDENTIFIER: &str = "eye";
+
+macro_rules! log {
+    ($level:expr, $($key:ident = $value:expr),+; $($arg:tt)+) => {
+        if $crate::log::enabled($level) {
+            $crate::log::write($level, module_path!(), &[$((stringify!($key), &$value as &dyn std::fmt::Display)),+], format_args!($($arg)+));
+        }
+    };
+    ($level:expr, $($arg:tt)+) => {
+        if $crate::log::enabled($level) {
+            $crate::log::write($level, module_path!(), &[], format_args!($($arg)+));
+        }
+    };
+}
+
+macro_rules! error {
+    ($($arg:tt)+) => { log!($crate::log::Level::Error, $($arg)+) };
+}
+
+macro_rules! warn {
+    ($($arg:tt)+) => { log!($crate::log::Level::Warn, $($arg)+) };
+}
+
+macro_rules! info {
+    ($($arg:tt)+) => { log!($crate::log::Level::Info, $($arg)+) };
+}
+
+macro_rules! debug {
+    ($($arg:tt)+) => { log!($crate::log::Level::Debug, $($arg)+) };
+}
+
+macro_rules! trace {
+    ($($arg:tt)+) => { log!($crate::log::Level::Trace, $($arg)+) };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sink {
+    Stderr,
+    File,
+    Journald,
+}
+
+struct Logger {
+    level: Level,
+    stderr: bool,
+    file: Option<Mutex<RotatingFile>>,
+    journald: Option<UnixDatagram>,
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+pub fn init(settings: &LogSettings, file: Option<PathBuf>) -> Result<()> {
+    let file = match (settings.sinks.contains(&Sink::File), file) {
+        (true, Some(path)) => Some(Mutex::new(RotatingFile::new(path, settings.max_size, settings.max_files))),
+        (true, None) => return Err(std::io::Error::new(ErrorKind::NotFound, "There is no state directory for the log file")),
+        (false, _) => None,
+    };
+    let journald = match settings.sinks.contains(&Sink::Journald) {
+        true => {
+            let socket = UnixDatagram::unbound()?;
+            socket.connect(JOURNALD_SOCKET)?;
+            Some(socket)
+        },
+        false => None,
+    };
+
+    let logger = Logger {
+        level: settings.level,
+        stderr: settings.sinks.contains(&Sink::Stderr),
+        file,
+        journald,
+    };
+    LOGGER.set(logger).map_err(|_| std::io::Error::new(ErrorKind::AlreadyExists, "Logging is already initialized"))
+}
+
+pub fn enabled(level: Level) -> bool {
+    level <= LOGGER.get().map_or(Level::Info, |logger| logger.level)
+}
+
+pub fn write(level: Level, module: &str, fields: &[(&str, &dyn Display)], args: Arguments) {
+    let target = module.split_once("::").map_or(module, |(_, target)| target);
+    let message = args.to_string();
+    let logger = match LOGGER.get() {
+        Some(logger) => logger,
+        None => {
+            eprintln!("{}", format_line(level, target, &message, fields));
+            return;
+        },
+    };
+
+    if logger.stderr || logger.file.is_some() {
+        let line = format_line(level, target, &message, fields);
+        if logger.stderr {
+            eprintln!("{}", line);
+        }
+        if let Some(file) = &logger.file {
+            let file = file.lock().unwrap_or_else(|e| e.into_inner());
+            if let Err(e) = file.append(&format!("{}\n", line)) {
+                eprintln!("Failed to write log file {}: {}", file.path().display(), e);
+            }
+        }
+    }
+
+    if let Some(journald) = &logger.journald {
+        journald.send(&journal_entry(level, target, &message, fields)).unwrap_or_default();
+    }
+}
+
+fn format_line(level: Level, target: &str, message: &str, fields: &[(&str, &dyn Display)]) -> String {
+    let mut line = format!("{} {:<5} {}: {}", timestamp(), level.name().to_uppercase(), target, message);
+    for (key, value) in fields {
+        let value = value.to_string();
+        if value.is_empty() || value.contains(|c: char| c.is_whitespace() || c == '"' || c == '=') {
+            write!(line, " {}={:?}", key, value).unwrap_or_default();
+        } else {
+            write!(line, " {}={}", key, value).unwrap_or_default();
+        }
+    }
+
+    line
+}
+
+fn journal_entry(level: Level, target: &str, message: &str, fields: &[(&str, &dyn Display)]) -> Vec<u8> {
+    let mut entry = Vec::new();
+    let mut add_field = |key: &str, value: &str| {
+        entry.extend_from_slice(key.as_bytes());
+        if value.contains('\n') {
+            entry.push(b'\n');
+            entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            entry.push(b'=');
+        }
+        entry.extend_from_slice(value.as_bytes());
+        entry.push(b'\n');
+    };
+
+    add_field("PRIORITY", level.priority());
+    add_field("SYSLOG_IDENTIFIER", SYSLOG_IDENTIFIER);
+    add_field("TARGET", target);
+    add_field("MESSAGE", message);
+    for (key, value) in fields {
+        add_field(&key.to_uppercase(), &value.to_string());
+    }
+
+    entry
+}
+
+fn timestamp() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe {
+        let time = now.as_secs() as libc::time_t;
+        libc::localtime_r(&time, &mut tm);
+    }
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03}",
+        tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday, tm.tm_hour, tm.tm_min, tm.tm_sec, now.subsec_millis(),
+    )
+}
+
+impl Level {
+    fn name(&self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+
+    fn priority(&self) -> &'static str {
+        match self {
+            Level::Error => "3",
+            Level::Warn => "4",
+            Level::Info => "6",
+            Level::Debug | Level::Trace => "7",
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<Self, Self::Err> {
+        [Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace].iter()
+            .copied()
+            .find(|level| level.name() == text)
+            .ok_or_else(|| format!("Invalid log level {}, expected error, warn, info, debug or trace", text))
+    }
+}
+
+impl FromStr for Sink {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<Self, Self::Err> {
+        match text {
+            "stderr" => Ok(Sink::Stderr),
+            "file" => Ok(Sink::File),
+            "journald" => Ok(Sink::Journald),
+            _ => Err(format!("Invalid log sink {}, expected stderr, file or journald", text)),
+        }
+    }
+}
@@ -1,37 +1,190 @@
+#[macro_use]
+mod log;
 mod networking;
 mod camera;
+mod config;
 mod server;
 mod fs;
 mod servo;
+mod mjpeg;
+mod stream_proxy;
+mod recordings;
+mod settings;
+mod patrol;
+mod scheduler;
+mod events;
+mod tracking;
+mod history;
+mod rotating_file;
+mod metrics;
+mod diagnostics;
 
 use std::net::Ipv4Addr;
 
 use pnet::ipnetwork::IpNetwork;
-use server::Server;
+use server::{Server, Services};
 
 
 #[tokio::main]
 async fn main() {
     let fs = fs::Fs::new().expect("Failed to initalize filesystem helpers");
-    let camera = camera::init_camera(fs).expect("Failed to initialize camera");
-    let servo = match servo::init() {
+    let settings = settings::Settings::load(&fs).expect("Failed to load settings");
+    if let Err(e) = settings.log().and_then(|log_settings| log::init(&log_settings, fs.log_file().ok())) {
+        error!("Failed to set up logging: {}", e);
+    }
+    if is_self_test() {
+        let success = self_test(&fs, &settings).await;
+        std::process::exit(if success { 0 } else { 1 });
+    }
+
+    match settings.metrics_port() {
+        Ok(Some(port)) => match tokio::net::TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)).await {
+            Ok(listener) => {
+                info!("Serving metrics on port {}", port);
+                tokio::spawn(metrics::serve(listener));
+            },
+            Err(e) => error!("Failed to serve metrics on port {}: {}", port, e),
+        },
+        Ok(None) => {},
+        Err(e) => error!("Failed to read metrics settings: {}", e),
+    }
+    let servo_settings = match settings.servo() {
+        Ok(servo_settings) => servo_settings,
+        Err(e) => {
+            error!("Failed to read servo settings: {}", e);
+            std::process::exit(1);
+        },
+    };
+    let camera_backend = get_camera_override();
+    let uses_fake_camera = match camera_backend.as_deref() {
+        Some(backend) => backend == "fake",
+        None => settings.cameras().is_ok_and(|cameras| cameras.iter().any(|camera| camera.backend == "fake")),
+    };
+    let servo = match servo_settings.simulated {
+        true => servo::simulated(&servo_settings),
+        false => servo::init(&servo_settings),
+    };
+    let servo = match servo {
+        Ok(s) if s.is_simulated() => {
+            info!("Using a simulated servo");
+            Some(s)
+        },
         Ok(s) => {
-            println!("Servo initialized successfully");
+            info!("Servo initialized successfully");
             Some(s)
         },
+        Err(servo::Error::ServoNotEnabled) if uses_fake_camera => {
+            warn!("Servo feature is not enabled, using a simulated servo for the fake camera");
+            servo::simulated(&servo_settings).ok()
+        },
         Err(e) => {
-            eprintln!("Failed to initialize servo");
-            eprintln!("{}", e);
+            error!("Failed to initialize servo: {}", e);
             None
         },
     };
+    let patrol_settings = match settings.patrol(&servo_settings) {
+        Ok(patrol_settings) => patrol_settings,
+        Err(e) => {
+            error!("Failed to read patrol settings: {}", e);
+            std::process::exit(1);
+        },
+    };
+    let patrol = patrol::Patrol::new(patrol_settings, servo.clone());
+    let scheduler = match scheduler::Scheduler::new(&settings.schedule()) {
+        Ok(scheduler) => scheduler,
+        Err(e) => {
+            error!("Failed to read schedule: {}", e);
+            std::process::exit(1);
+        },
+    };
+    let motion_events = match fs.event_socket_file().and_then(|path| events::listen(&path)) {
+        Ok(motion_events) => Some(motion_events),
+        Err(e) => {
+            error!("Failed to listen for motion events: {}", e);
+            None
+        },
+    };
+    let cameras = match camera::init_cameras(&fs, &settings, camera_backend.as_deref(), servo.as_ref()) {
+        Ok(cameras) => cameras,
+        Err(e) => {
+            error!("Failed to initialize cameras: {}", e);
+            std::process::exit(1);
+        },
+    };
 
-    let server = Server::new(camera, servo);
+    let tracker = match settings.cameras().and_then(|cameras| tracking::Tracker::new(&cameras, &servo_settings.presets, servo.clone())) {
+        Ok(tracker) => tracker,
+        Err(e) => {
+            error!("Failed to set up motion tracking: {}", e);
+            std::process::exit(1);
+        },
+    };
+
+    let history = match settings.history().and_then(|history| Ok(history::History::new(fs.history_file()?, history.max_size, history.max_files))) {
+        Ok(history) => history,
+        Err(e) => {
+            error!("Failed to set up event history: {}", e);
+            std::process::exit(1);
+        },
+    };
+
+    let services = Services { patrol, scheduler, tracker, history, motion_events };
+    let server = Server::new(settings, cameras, servo, services);
     if let Err(e) = server.start().await {
-        eprintln!("Server failed: {}", e);
+        error!("Server failed: {}", e);
+    }
+
+    if let Ok(path) = fs.event_socket_file() {
+        std::fs::remove_file(path).unwrap_or_default();
+    }
+    info!("Shutdown complete");
+}
+
+
+async fn self_test(fs: &fs::Fs, settings: &settings::Settings) -> bool {
+    let mut cameras = match settings.cameras() {
+        Ok(cameras) => cameras,
+        Err(e) => {
+            error!("Failed to read camera settings: {}", e);
+            Vec::new()
+        },
+    };
+    if let Some(backend) = get_camera_override() {
+        cameras.iter_mut().for_each(|camera| camera.backend = backend.clone());
     }
+
+    let uses_motion = cameras.iter().any(|camera| camera.backend == "motion");
+    let stream_ports = cameras.iter()
+        .filter(|camera| camera.backend != "rtsp")
+        .map(|camera| diagnostics::StreamPort { camera_id: camera.id, name: "stream", port: camera.stream_port })
+        .collect::<Vec<_>>();
+
+    let checks = diagnostics::run(fs, settings, uses_motion, &stream_ports).await;
+    for check in &checks {
+        println!("[{}] {}: {}", if check.success { " OK " } else { "FAIL" }, check.name, check.details);
+    }
+
+    checks.iter().all(|check| check.success)
 }
 
+fn is_self_test() -> bool {
+    std::env::args().skip(1).any(|arg| arg == "--self-test")
+}
+
+fn get_camera_override() -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--camera" {
+            return args.next();
+        }
+
+        if let Some(backend) = arg.strip_prefix("--camera=") {
+            return Some(backend.to_owned());
+        }
+    }
+
+    None
+}
 
 fn get_current_ip_address() -> Ipv4Addr {
     let interface = pnet::datalink::interfaces()
@@ -0,0 +1,198 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::Result;
+use std::sync::Mutex;
+use std::time::Instant;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::camera::State;
+
+const MAX_HEADER_LINES: usize = 64;
+
+struct Registry {
+    connected_clients: u64,
+    messages: BTreeMap<&'static str, u64>,
+    decode_failures: BTreeMap<&'static str, u64>,
+    servo_moves: BTreeMap<u32, u64>,
+    servo_angles: BTreeMap<(u32, &'static str), u8>,
+    cameras: BTreeMap<u32, CameraMetrics>,
+    i2c_errors: BTreeMap<u8, u64>,
+}
+
+#[derive(Default)]
+struct CameraMetrics {
+    running_since: Option<Instant>,
+    restarts: u64,
+    has_failed: bool,
+}
+
+static METRICS: Mutex<Registry> = Mutex::new(Registry {
+    connected_clients: 0,
+    messages: BTreeMap::new(),
+    decode_failures: BTreeMap::new(),
+    servo_moves: BTreeMap::new(),
+    servo_angles: BTreeMap::new(),
+    cameras: BTreeMap::new(),
+    i2c_errors: BTreeMap::new(),
+});
+
+fn registry() -> std::sync::MutexGuard<'static, Registry> {
+    METRICS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub fn client_connected() {
+    registry().connected_clients += 1;
+}
+
+pub fn client_disconnected() {
+    let mut registry = registry();
+    registry.connected_clients = registry.connected_clients.saturating_sub(1);
+}
+
+pub fn message_handled(message_type: &'static str) {
+    *registry().messages.entry(message_type).or_default() += 1;
+}
+
+pub fn decode_failed(message_type: &'static str) {
+    *registry().decode_failures.entry(message_type).or_default() += 1;
+}
+
+pub fn servo_moved(rig: u32) {
+    *registry().servo_moves.entry(rig).or_default() += 1;
+}
+
+pub fn servo_angle(rig: u32, axis: &'static str, angle: u8) {
+    registry().servo_angles.insert((rig, axis), angle);
+}
+
+pub fn camera_state(camera_id: u32, state: &State) {
+    let mut registry = registry();
+    let camera = registry.cameras.entry(camera_id).or_default();
+    match state {
+        State::Running => {
+            if camera.running_since.is_none() {
+                camera.running_since = Some(Instant::now());
+                if camera.has_failed {
+                    camera.restarts += 1;
+                    camera.has_failed = false;
+                }
+            }
+        },
+        State::Failed(_) => {
+            camera.running_since = None;
+            camera.has_failed = true;
+        },
+        State::Stopped | State::Stopping => {
+            camera.running_since = None;
+            camera.has_failed = false;
+        },
+        State::Starting => {},
+    }
+}
+
+#[cfg_attr(not(feature = "servo"), allow(dead_code))]
+pub fn i2c_error(board: u8) {
+    *registry().i2c_errors.entry(board).or_default() += 1;
+}
+
+pub async fn serve(listener: TcpListener) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, address)) => {
+                tokio::spawn(async move {
+                    if let Err(e) = serve_scrape(stream).await {
+                        debug!("Metrics scrape from {} failed: {}", address, e);
+                    }
+                });
+            },
+            Err(e) => error!("Failed to accept metrics connection: {}", e),
+        }
+    }
+}
+
+async fn serve_scrape(stream: TcpStream) -> Result<()> {
+    let mut stream = BufReader::new(stream);
+    let mut request = String::new();
+    stream.read_line(&mut request).await?;
+
+    let mut line = String::new();
+    for _ in 0..MAX_HEADER_LINES {
+        line.clear();
+        if stream.read_line(&mut line).await? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    let path = request.split_whitespace().nth(1).unwrap_or_default();
+    let response = match path.split('?').next() {
+        Some("/metrics") => {
+            let body = render();
+            format!(
+                "HTTP/1.0 200 OK\r\n\
+                Content-Type: text/plain; version=0.0.4\r\n\
+                Content-Length: {}\r\n\r\n{}",
+                body.len(), body)
+        },
+        _ => String::from("HTTP/1.0 404 Not Found\r\nContent-Length: 0\r\n\r\n"),
+    };
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+fn render() -> String {
+    let registry = registry();
+    let mut text = String::new();
+
+    header(&mut text, "eye_connected_clients", "gauge", "Number of connected control clients.");
+    writeln!(text, "eye_connected_clients {}", registry.connected_clients).unwrap_or_default();
+
+    header(&mut text, "eye_messages_total", "counter", "Control messages handled, by message type.");
+    for (message_type, count) in &registry.messages {
+        writeln!(text, "eye_messages_total{{type=\"{}\"}} {}", message_type, count).unwrap_or_default();
+    }
+
+    header(&mut text, "eye_message_decode_failures_total", "counter", "Control messages that failed to decode, by message type.");
+    for (message_type, count) in &registry.decode_failures {
+        writeln!(text, "eye_message_decode_failures_total{{type=\"{}\"}} {}", message_type, count).unwrap_or_default();
+    }
+
+    header(&mut text, "eye_servo_moves_total", "counter", "Servo rig movements started, by rig.");
+    for (rig, count) in &registry.servo_moves {
+        writeln!(text, "eye_servo_moves_total{{rig=\"{}\"}} {}", rig, count).unwrap_or_default();
+    }
+
+    header(&mut text, "eye_servo_angle_degrees", "gauge", "Current servo angle, by rig and axis.");
+    for ((rig, axis), angle) in &registry.servo_angles {
+        writeln!(text, "eye_servo_angle_degrees{{rig=\"{}\",axis=\"{}\"}} {}", rig, axis, angle).unwrap_or_default();
+    }
+
+    header(&mut text, "eye_camera_up", "gauge", "Whether the camera is running.");
+    for (camera_id, camera) in &registry.cameras {
+        writeln!(text, "eye_camera_up{{camera=\"{}\"}} {}", camera_id, camera.running_since.is_some() as u8).unwrap_or_default();
+    }
+
+    header(&mut text, "eye_camera_uptime_seconds", "gauge", "Seconds since the camera last started.");
+    for (camera_id, camera) in &registry.cameras {
+        let uptime = camera.running_since.map_or(0.0, |since| since.elapsed().as_secs_f64());
+        writeln!(text, "eye_camera_uptime_seconds{{camera=\"{}\"}} {:.3}", camera_id, uptime).unwrap_or_default();
+    }
+
+    header(&mut text, "eye_camera_restarts_total", "counter", "Camera restarts after a failure.");
+    for (camera_id, camera) in &registry.cameras {
+        writeln!(text, "eye_camera_restarts_total{{camera=\"{}\"}} {}", camera_id, camera.restarts).unwrap_or_default();
+    }
+
+    header(&mut text, "eye_i2c_errors_total", "counter", "Failed I2C transfers to PCA9685 boards, by board.");
+    for (board, count) in &registry.i2c_errors {
+        writeln!(text, "eye_i2c_errors_total{{board=\"{}\"}} {}", board, count).unwrap_or_default();
+    }
+
+    text
+}
+
+fn header(text: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(text, "# HELP {} {}", name, help).unwrap_or_default();
+    writeln!(text, "# TYPE {} {}", name, kind).unwrap_or_default();
+}
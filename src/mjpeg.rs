@@ -0,0 +1,143 @@
+use bytes::Bytes;
+use std::io::{Error, ErrorKind, Result};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+const BOUNDARY: &str = "eyeframe";
+const MAX_HEADER_LINES: usize = 64;
+
+pub struct FrameReader<R> {
+    reader: BufReader<R>,
+    line: String,
+}
+
+impl<R> FrameReader<R> where R: AsyncRead + std::marker::Unpin {
+    pub async fn new(reader: R) -> Result<Self> {
+        let mut frame_reader = FrameReader { reader: BufReader::new(reader), line: String::new() };
+
+        let status = frame_reader.read_line().await?;
+        if !status.starts_with("HTTP/") || !status.contains(" 200") {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Unexpected stream response: {}", status)));
+        }
+
+        frame_reader.skip_headers().await?;
+        Ok(frame_reader)
+    }
+
+    pub async fn next_frame(&mut self) -> Result<Option<Bytes>> {
+        loop {
+            let line = self.read_line().await?;
+            if line.is_empty() && self.line.is_empty() {
+                return Ok(None);
+            }
+
+            if line.starts_with("--") {
+                break;
+            }
+        }
+
+        let mut content_length = None;
+        for _ in 0..MAX_HEADER_LINES {
+            let line = self.read_line().await?;
+            if line.is_empty() {
+                break;
+            }
+
+            if let Some((name, value)) = line.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse::<usize>().ok();
+                }
+            }
+        }
+
+        let length = content_length.ok_or_else(|| Error::new(ErrorKind::InvalidData, "Stream part has no Content-Length"))?;
+        let mut frame = vec![0_u8; length];
+        self.reader.read_exact(&mut frame).await?;
+
+        Ok(Some(Bytes::from(frame)))
+    }
+
+    async fn skip_headers(&mut self) -> Result<()> {
+        for _ in 0..MAX_HEADER_LINES {
+            if self.read_line().await?.is_empty() {
+                return Ok(());
+            }
+        }
+
+        Err(Error::new(ErrorKind::InvalidData, "Too many headers"))
+    }
+
+    async fn read_line(&mut self) -> Result<String> {
+        self.line.clear();
+        self.reader.read_line(&mut self.line).await?;
+        Ok(self.line.trim_end().to_owned())
+    }
+}
+
+pub async fn serve(listener: TcpListener, frames: broadcast::Sender<Bytes>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, address)) => {
+                let receiver = frames.subscribe();
+                tokio::spawn(async move {
+                    if let Err(e) = serve_viewer(stream, receiver).await {
+                        debug!("Stream viewer {} disconnected: {}", address, e);
+                    }
+                });
+            },
+            Err(e) => error!("Failed to accept stream viewer: {}", e),
+        }
+    }
+}
+
+pub async fn serve_viewer<S>(stream: S, mut frames: broadcast::Receiver<Bytes>) -> Result<()>
+    where S: AsyncRead + AsyncWrite + std::marker::Unpin {
+
+    let mut stream = BufReader::new(stream);
+    let mut line = String::new();
+    for _ in 0..MAX_HEADER_LINES {
+        line.clear();
+        if stream.read_line(&mut line).await? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    let header = format!(
+        "HTTP/1.0 200 OK\r\n\
+        Cache-Control: no-cache, private\r\n\
+        Pragma: no-cache\r\n\
+        Content-Type: multipart/x-mixed-replace; boundary={}\r\n\r\n",
+        BOUNDARY);
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    writer.write_all(header.as_bytes()).await?;
+
+    let mut discarded = [0_u8; 256];
+    loop {
+        tokio::select! {
+            frame = frames.recv() => match frame {
+                Ok(frame) => write_frame(&mut writer, &frame).await?,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            },
+            read = reader.read(&mut discarded) => if read? == 0 {
+                break;
+            },
+        }
+    }
+
+    Ok(())
+}
+
+async fn write_frame<W>(writer: &mut W, frame: &[u8]) -> Result<()>
+    where W: AsyncWrite + std::marker::Unpin {
+
+    let part_header = format!(
+        "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+        BOUNDARY,
+        frame.len());
+    writer.write_all(part_header.as_bytes()).await?;
+    writer.write_all(frame).await?;
+    writer.write_all(b"\r\n").await?;
+    writer.flush().await
+}
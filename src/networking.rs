@@ -6,14 +6,78 @@ use std::io::Cursor;
 pub enum MessageType {
     HelloRequest,
     HelloResponse,
-    ServoRotateRequest
+    ServoRotateRequest,
+    CameraParameterGetRequest,
+    CameraParameterSetRequest,
+    CameraParameterResponse,
+    DetectionSetRequest,
+    DetectionStatusRequest,
+    DetectionStatusResponse,
+    SnapshotRequest,
+    SnapshotResponse,
+    RecordingListRequest,
+    RecordingListResponse,
+    RecordingDownloadRequest,
+    RecordingChunk,
+    RecordingDeleteRequest,
+    RecordingDeleteResponse,
+    CameraStatusRequest,
+    CameraStatusResponse,
+    ServoPositionRequest,
+    ServoVelocityRequest,
+    PatrolStartRequest,
+    PatrolStopRequest,
+    PatrolStatusRequest,
+    PatrolStatusResponse,
+    ScheduleListRequest,
+    ScheduleListResponse,
+    ScheduleAddRequest,
+    ScheduleRemoveRequest,
+    MotionEvent,
+    EventHistoryRequest,
+    EventHistoryResponse,
+    DiagnosticsRequest,
+    DiagnosticsResponse,
+    ServerShutdown
 }
 
 thread_local! {
     static MESSAGES_LOOKUP: Vec<MessageType> = vec![
         MessageType::HelloRequest,
         MessageType::HelloResponse,
-        MessageType::ServoRotateRequest
+        MessageType::ServoRotateRequest,
+        MessageType::CameraParameterGetRequest,
+        MessageType::CameraParameterSetRequest,
+        MessageType::CameraParameterResponse,
+        MessageType::DetectionSetRequest,
+        MessageType::DetectionStatusRequest,
+        MessageType::DetectionStatusResponse,
+        MessageType::SnapshotRequest,
+        MessageType::SnapshotResponse,
+        MessageType::RecordingListRequest,
+        MessageType::RecordingListResponse,
+        MessageType::RecordingDownloadRequest,
+        MessageType::RecordingChunk,
+        MessageType::RecordingDeleteRequest,
+        MessageType::RecordingDeleteResponse,
+        MessageType::CameraStatusRequest,
+        MessageType::CameraStatusResponse,
+        MessageType::ServoPositionRequest,
+        MessageType::ServoVelocityRequest,
+        MessageType::PatrolStartRequest,
+        MessageType::PatrolStopRequest,
+        MessageType::PatrolStatusRequest,
+        MessageType::PatrolStatusResponse,
+        MessageType::ScheduleListRequest,
+        MessageType::ScheduleListResponse,
+        MessageType::ScheduleAddRequest,
+        MessageType::ScheduleRemoveRequest,
+        MessageType::MotionEvent,
+        MessageType::EventHistoryRequest,
+        MessageType::EventHistoryResponse,
+        MessageType::DiagnosticsRequest,
+        MessageType::DiagnosticsResponse,
+        MessageType::ServerShutdown
     ];
 }
 
@@ -23,8 +87,8 @@ pub async fn send_message<Msg, W>(writer: &mut W, msg_type: MessageType, message
 
     if let Some(id) = msg_id_from_type(msg_type) {
         let mut cursor = Cursor::new(vec![]);
-        cursor.write(&u32::to_le_bytes(id)).await?;
-        cursor.write(&u32::to_le_bytes(message.encoded_len() as u32)).await?;
+        cursor.write_all(&u32::to_le_bytes(id)).await?;
+        cursor.write_all(&u32::to_le_bytes(message.encoded_len() as u32)).await?;
         cursor.write_all(&mut message.encode_to_vec()).await?;
 
         writer.write_all(&mut cursor.into_inner()).await?;
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::sync::mpsc::{self, Sender, Receiver};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::servo::Servo;
+use crate::settings::{PatrolSettings, TourSettings, WaypointSettings};
+
+const PATROL_INTERRUPT_BUFFER_SIZE: usize = 4;
+
+#[derive(Debug)]
+pub enum Error {
+    ServoNotAvailable,
+    UnknownTour,
+    UnknownPreset,
+}
+
+pub struct TourStatus<'a> {
+    pub tour: &'a TourSettings,
+    pub is_active: bool,
+    pub is_paused: bool,
+}
+
+pub struct Patrol {
+    settings: PatrolSettings,
+    servo: Option<Servo>,
+    active: HashMap<u32, ActiveTour>,
+}
+
+struct ActiveTour {
+    name: String,
+    interrupts: Sender<()>,
+    is_paused: Arc<AtomicBool>,
+    task: JoinHandle<()>,
+}
+
+impl Patrol {
+    pub fn new(settings: PatrolSettings, servo: Option<Servo>) -> Self {
+        Patrol { settings, servo, active: HashMap::new() }
+    }
+
+    pub fn tours(&self) -> impl Iterator<Item = TourStatus<'_>> {
+        self.settings.tours.iter().map(move |tour| {
+            let active = self.active.get(&tour.rig).filter(|active| active.name == tour.name);
+            TourStatus {
+                tour,
+                is_active: active.is_some(),
+                is_paused: active.is_some_and(|active| active.is_paused.load(Ordering::Acquire)),
+            }
+        })
+    }
+
+    pub fn start(&mut self, name: &str) -> Result<(), Error> {
+        let tour = self.settings.tours.iter()
+            .find(|tour| tour.name == name)
+            .ok_or(Error::UnknownTour)?
+            .clone();
+        let servo = self.servo.clone().ok_or(Error::ServoNotAvailable)?;
+        let motion = servo.motion(tour.rig).ok_or(Error::ServoNotAvailable)?;
+
+        self.stop_rig(tour.rig);
+        info!("Starting patrol {} on rig {}", tour.name, tour.rig);
+
+        let (interrupts, receiver) = mpsc::channel(PATROL_INTERRUPT_BUFFER_SIZE);
+        let is_paused = Arc::new(AtomicBool::new(false));
+        let rig = tour.rig;
+        let active = ActiveTour {
+            name: tour.name.clone(),
+            interrupts,
+            is_paused: is_paused.clone(),
+            task: tokio::spawn(patrol_routine(servo, motion, tour, receiver, self.settings.resume_delay, is_paused)),
+        };
+        self.active.insert(rig, active);
+        Ok(())
+    }
+
+    pub fn stop(&mut self, name: &str) -> Result<(), Error> {
+        if !name.is_empty() && !self.settings.tours.iter().any(|tour| tour.name == name) {
+            return Err(Error::UnknownTour);
+        }
+
+        let rigs = self.active.iter()
+            .filter(|(_, active)| name.is_empty() || active.name == name)
+            .map(|(rig, _)| *rig)
+            .collect::<Vec<_>>();
+        for rig in rigs {
+            self.stop_rig(rig);
+        }
+        Ok(())
+    }
+
+    pub fn go_to_preset(&mut self, name: &str) -> Result<(), Error> {
+        let preset = self.settings.presets.iter()
+            .find(|preset| preset.name == name)
+            .ok_or(Error::UnknownPreset)?
+            .clone();
+
+        self.stop_rig(preset.rig);
+        self.servo.as_mut()
+            .ok_or(Error::ServoNotAvailable)?
+            .move_to(preset.rig, preset.pan, preset.tilt, None)
+            .map_err(|_| Error::ServoNotAvailable)
+    }
+
+    pub fn manual_control(&self, rig: u32) {
+        if let Some(active) = self.active.get(&rig) {
+            active.interrupts.try_send(()).unwrap_or_default();
+        }
+    }
+
+    fn stop_rig(&mut self, rig: u32) {
+        if let Some(active) = self.active.remove(&rig) {
+            info!("Stopping patrol {} on rig {}", active.name, rig);
+            active.task.abort();
+            if let Some(servo) = self.servo.as_mut() {
+                servo.set_velocity(rig, 0.0, 0.0).unwrap_or_default();
+            }
+        }
+    }
+}
+
+async fn patrol_routine(mut servo: Servo, mut motion: watch::Receiver<bool>, tour: TourSettings, mut interrupts: Receiver<()>, resume_delay: Duration, is_paused: Arc<AtomicBool>) {
+    for waypoint in tour.waypoints.iter().cycle() {
+        loop {
+            let is_completed = tokio::select! {
+                _ = visit(&mut servo, &mut motion, tour.rig, waypoint) => true,
+                _ = interrupts.recv() => false,
+            };
+            if is_completed {
+                break;
+            }
+
+            info!("Pausing patrol {} for manual control", tour.name);
+            is_paused.store(true, Ordering::Release);
+            loop {
+                match tokio::time::timeout(resume_delay, interrupts.recv()).await {
+                    Ok(Some(())) => continue,
+                    Ok(None) => return,
+                    Err(_) => break,
+                }
+            }
+            is_paused.store(false, Ordering::Release);
+            info!("Resuming patrol {}", tour.name);
+        }
+    }
+}
+
+async fn visit(servo: &mut Servo, motion: &mut watch::Receiver<bool>, rig: u32, waypoint: &WaypointSettings) {
+    motion.borrow_and_update();
+    let preset = &waypoint.preset;
+    if let Err(e) = servo.move_to(rig, preset.pan, preset.tilt, waypoint.speed) {
+        error!("Failed to move rig {} to preset {}: {}", rig, preset.name, e);
+    }
+
+    while motion.changed().await.is_ok() {
+        if !*motion.borrow() {
+            break;
+        }
+    }
+
+    tokio::time::sleep(waypoint.dwell).await;
+}
+
+impl Display for Error {
+    fn fmt(
+        &self,
+        formatter: &mut std::fmt::Formatter<'_>,
+    ) -> std::result::Result<(), std::fmt::Error> {
+        let message = match self {
+            Error::ServoNotAvailable => "Servo is not available",
+            Error::UnknownTour => "There is no patrol tour with this name",
+            Error::UnknownPreset => "There is no servo preset with this name",
+        };
+        write!(formatter, "{}", message)
+    }
+}
@@ -0,0 +1,94 @@
+use std::io::{Error, ErrorKind, Read, Result, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const RECORDING_EXTENSIONS: [&str; 5] = ["mkv", "mp4", "avi", "mov", "jpg"];
+
+pub struct Recording {
+    pub name: String,
+    pub size: u64,
+    pub timestamp: u64,
+    pub duration: Option<u32>,
+}
+
+pub struct Chunk {
+    pub data: Vec<u8>,
+    pub total_size: u64,
+}
+
+pub fn list(dir: &Path) -> Result<Vec<Recording>> {
+    let mut recordings = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let is_recording = has_recording_extension(&path);
+        let metadata = entry.metadata()?;
+        if !is_recording || !metadata.is_file() {
+            continue;
+        }
+
+        let modified = metadata.modified().ok();
+        let born = metadata.created().ok();
+        let created = born.or(modified);
+        // Estimated from the file's birth and modification times, which only hold for files motion wrote in place.
+        let duration = match (born, modified) {
+            (Some(b), Some(m)) if !is_snapshot(&path) => m.duration_since(b).ok().map(|d| d.as_secs() as u32),
+            _ => None,
+        };
+
+        recordings.push(Recording {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            size: metadata.len(),
+            timestamp: created.map_or(0, unix_time),
+            duration,
+        });
+    }
+
+    recordings.sort_by_key(|r| r.timestamp);
+    Ok(recordings)
+}
+
+pub fn read_chunk(dir: &Path, name: &str, offset: u64, max_length: usize) -> Result<Chunk> {
+    let mut file = std::fs::File::open(resolve(dir, name)?)?;
+    let total_size = file.metadata()?.len();
+
+    file.seek(SeekFrom::Start(offset.min(total_size)))?;
+    let mut data = Vec::with_capacity(max_length);
+    file.take(max_length as u64).read_to_end(&mut data)?;
+
+    Ok(Chunk { data, total_size })
+}
+
+pub fn delete(dir: &Path, name: &str) -> Result<()> {
+    std::fs::remove_file(resolve(dir, name)?)
+}
+
+fn resolve(dir: &Path, name: &str) -> Result<PathBuf> {
+    let is_plain_name = !name.is_empty()
+        && !name.starts_with('.')
+        && !name.contains(['/', '\\']);
+    if !is_plain_name || !has_recording_extension(Path::new(name)) {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("Invalid recording name {}", name)));
+    }
+
+    let path = dir.join(name);
+    if !std::fs::symlink_metadata(&path)?.is_file() {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("{} is not a recording", name)));
+    }
+
+    Ok(path)
+}
+
+fn has_recording_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| RECORDING_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+}
+
+fn is_snapshot(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e.eq_ignore_ascii_case("jpg"))
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
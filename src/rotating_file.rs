@@ -0,0 +1,67 @@
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Result, Write};
+use std::path::{Path, PathBuf};
+
+pub struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    max_files: u32,
+}
+
+impl RotatingFile {
+    pub fn new(path: PathBuf, max_size: u64, max_files: u32) -> Self {
+        RotatingFile { path, max_size, max_files }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, line: &str) -> Result<()> {
+        self.rotate_if_full()?;
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(line.as_bytes())
+    }
+
+    pub fn paths_oldest_first(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        (0..=self.max_files).rev().map(move |index| self.rotated_path(index))
+    }
+
+    fn rotate_if_full(&self) -> Result<()> {
+        let size = match std::fs::metadata(&self.path) {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if size < self.max_size {
+            return Ok(());
+        }
+
+        for index in (1..self.max_files).rev() {
+            rename_if_exists(&self.rotated_path(index), &self.rotated_path(index + 1))?;
+        }
+
+        if self.max_files == 0 {
+            std::fs::remove_file(&self.path)
+        } else {
+            std::fs::rename(&self.path, self.rotated_path(1))
+        }
+    }
+
+    fn rotated_path(&self, index: u32) -> PathBuf {
+        match index {
+            0 => self.path.clone(),
+            _ => PathBuf::from(format!("{}.{}", self.path.display(), index)),
+        }
+    }
+}
+
+fn rename_if_exists(from: &Path, to: &Path) -> Result<()> {
+    match std::fs::rename(from, to) {
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
@@ -0,0 +1,383 @@
+use std::fmt::Display;
+use std::io::{Error, ErrorKind, Result};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const FIELD_RANGES: [(u32, u32); 5] = [(0, 59), (0, 23), (1, 31), (1, 12), (0, 7)];
+const MAX_MISSED_MINUTES: u64 = 60;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    CameraStart(Option<u32>),
+    CameraStop(Option<u32>),
+    Preset(String),
+    Patrol(String),
+    PatrolStop(Option<String>),
+    Snapshot(Option<u32>),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Field {
+    mask: u64,
+    is_any: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Schedule {
+    text: String,
+    minute: Field,
+    hour: Field,
+    day: Field,
+    month: Field,
+    weekday: Field,
+}
+
+pub struct Entry {
+    pub id: u32,
+    pub schedule: Schedule,
+    pub action: Action,
+}
+
+pub struct LocalTime {
+    minute: u32,
+    hour: u32,
+    day: u32,
+    month: u32,
+    weekday: u32,
+}
+
+pub struct Scheduler {
+    entries: Vec<Entry>,
+    next_id: u32,
+    last_minute: u64,
+}
+
+impl Scheduler {
+    pub fn new<S>(lines: &[S]) -> Result<Self> where S: AsRef<str> {
+        let mut scheduler = Scheduler { entries: Vec::new(), next_id: 0, last_minute: current_minute() };
+        for line in lines {
+            scheduler.add_line(line.as_ref())?;
+        }
+
+        Ok(scheduler)
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        self.entries.iter()
+            .map(|entry| format!("{} {}", entry.schedule, entry.action))
+            .collect()
+    }
+
+    pub fn add(&mut self, schedule: &str, action: &str) -> Result<u32> {
+        let entry = Entry {
+            id: self.next_id,
+            schedule: schedule.parse()?,
+            action: action.parse()?,
+        };
+
+        self.next_id += 1;
+        self.entries.push(entry);
+        Ok(self.next_id - 1)
+    }
+
+    pub fn remove(&mut self, id: u32) -> Result<()> {
+        let index = self.entries.iter()
+            .position(|entry| entry.id == id)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("There is no schedule entry {}", id)))?;
+
+        self.entries.remove(index);
+        Ok(())
+    }
+
+    pub fn due_actions(&mut self) -> Vec<Action> {
+        self.due_actions_until(current_minute())
+    }
+
+    fn due_actions_until(&mut self, minute: u64) -> Vec<Action> {
+        if minute <= self.last_minute {
+            return Vec::new();
+        }
+
+        let first_minute = (self.last_minute + 1).max(minute.saturating_sub(MAX_MISSED_MINUTES - 1));
+        self.last_minute = minute;
+
+        let mut actions = Vec::new();
+        for time in (first_minute..=minute).map(LocalTime::at_minute) {
+            for entry in self.entries.iter().filter(|entry| entry.schedule.matches(&time)) {
+                if !actions.contains(&entry.action) {
+                    actions.push(entry.action.clone());
+                }
+            }
+        }
+
+        actions
+    }
+
+    fn add_line(&mut self, line: &str) -> Result<()> {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        if fields.len() < 6 {
+            return Err(invalid("schedule", line));
+        }
+
+        self.add(&fields[..5].join(" "), &fields[5..].join(" "))?;
+        Ok(())
+    }
+}
+
+fn current_minute() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() / 60)
+}
+
+pub fn until_next_minute() -> Duration {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    Duration::from_secs(60) - Duration::from_millis((now.as_millis() % 60_000) as u64)
+}
+
+impl Schedule {
+    fn matches(&self, time: &LocalTime) -> bool {
+        let day_matches = match (self.day.is_any, self.weekday.is_any) {
+            (false, false) => self.day.contains(time.day) || self.weekday.contains(time.weekday),
+            _ => self.day.contains(time.day) && self.weekday.contains(time.weekday),
+        };
+
+        day_matches
+            && self.minute.contains(time.minute)
+            && self.hour.contains(time.hour)
+            && self.month.contains(time.month)
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let fields = text.split_whitespace().collect::<Vec<_>>();
+        if fields.len() != FIELD_RANGES.len() {
+            return Err(invalid("schedule", text));
+        }
+
+        let mut parsed = fields.iter()
+            .zip(FIELD_RANGES)
+            .map(|(field, (min, max))| Field::parse(field, min, max).ok_or_else(|| invalid("schedule field", field)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let weekday = &mut parsed[4];
+        if weekday.mask & (1 << 7) != 0 {
+            weekday.mask |= 1;
+        }
+
+        Ok(Schedule {
+            text: fields.join(" "),
+            minute: parsed[0],
+            hour: parsed[1],
+            day: parsed[2],
+            month: parsed[3],
+            weekday: parsed[4],
+        })
+    }
+}
+
+impl Display for Schedule {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(formatter, "{}", self.text)
+    }
+}
+
+impl Field {
+    fn parse(text: &str, min: u32, max: u32) -> Option<Self> {
+        let mut mask = 0_u64;
+        for part in text.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (range, step.parse::<u32>().ok().filter(|step| *step > 0)?),
+                None => (part, 1),
+            };
+
+            let (start, end) = match range {
+                "*" => (min, max),
+                _ => match range.split_once('-') {
+                    Some((start, end)) => (start.parse().ok()?, end.parse().ok()?),
+                    None => {
+                        let value = range.parse().ok()?;
+                        (value, if step > 1 { max } else { value })
+                    },
+                },
+            };
+
+            if start < min || end > max || start > end {
+                return None;
+            }
+
+            for value in (start..=end).step_by(step as usize) {
+                mask |= 1 << value;
+            }
+        }
+
+        Some(Field { mask, is_any: text == "*" })
+    }
+
+    fn contains(&self, value: u32) -> bool {
+        self.mask & (1 << value) != 0
+    }
+}
+
+impl LocalTime {
+    fn at_minute(minute: u64) -> Self {
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        unsafe {
+            let time = (minute * 60) as libc::time_t;
+            libc::localtime_r(&time, &mut tm);
+        }
+
+        LocalTime {
+            minute: tm.tm_min as u32,
+            hour: tm.tm_hour as u32,
+            day: tm.tm_mday as u32,
+            month: tm.tm_mon as u32 + 1,
+            weekday: tm.tm_wday as u32,
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let fields = text.split_whitespace().collect::<Vec<_>>();
+        let camera = || fields.get(1)
+            .map(|id| id.parse::<u32>().map_err(|_| invalid("schedule action", text)))
+            .transpose();
+        let name = || fields.get(1)
+            .map(|name| name.to_string())
+            .ok_or_else(|| invalid("schedule action", text));
+
+        if fields.len() > 2 {
+            return Err(invalid("schedule action", text));
+        }
+
+        match fields.first() {
+            Some(&"camera_start") => Ok(Action::CameraStart(camera()?)),
+            Some(&"camera_stop") => Ok(Action::CameraStop(camera()?)),
+            Some(&"preset") => Ok(Action::Preset(name()?)),
+            Some(&"patrol") => Ok(Action::Patrol(name()?)),
+            Some(&"patrol_stop") => Ok(Action::PatrolStop(fields.get(1).map(|name| name.to_string()))),
+            Some(&"snapshot") => Ok(Action::Snapshot(camera()?)),
+            _ => Err(invalid("schedule action", text)),
+        }
+    }
+}
+
+impl Display for Action {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let argument = |value: Option<String>| value.map_or_else(String::new, |value| format!(" {}", value));
+        match self {
+            Action::CameraStart(camera) => write!(formatter, "camera_start{}", argument(camera.map(|id| id.to_string()))),
+            Action::CameraStop(camera) => write!(formatter, "camera_stop{}", argument(camera.map(|id| id.to_string()))),
+            Action::Preset(name) => write!(formatter, "preset {}", name),
+            Action::Patrol(name) => write!(formatter, "patrol {}", name),
+            Action::PatrolStop(name) => write!(formatter, "patrol_stop{}", argument(name.clone())),
+            Action::Snapshot(camera) => write!(formatter, "snapshot{}", argument(camera.map(|id| id.to_string()))),
+        }
+    }
+}
+
+fn invalid(what: &str, text: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Invalid {} \"{}\"", what, text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(minute: u32, hour: u32, day: u32, month: u32, weekday: u32) -> LocalTime {
+        LocalTime { minute, hour, day, month, weekday }
+    }
+
+    fn values(field: &Field, min: u32, max: u32) -> Vec<u32> {
+        (min..=max).filter(|value| field.contains(*value)).collect()
+    }
+
+    #[test]
+    fn field_parses_ranges_steps_and_lists() {
+        assert_eq!(values(&Field::parse("5", 0, 59).unwrap(), 0, 59), [5]);
+        assert_eq!(values(&Field::parse("8-11", 0, 23).unwrap(), 0, 23), [8, 9, 10, 11]);
+        assert_eq!(values(&Field::parse("*/15", 0, 59).unwrap(), 0, 59), [0, 15, 30, 45]);
+        assert_eq!(values(&Field::parse("10-20/5", 0, 59).unwrap(), 0, 59), [10, 15, 20]);
+        assert_eq!(values(&Field::parse("50/4", 0, 59).unwrap(), 0, 59), [50, 54, 58]);
+        assert_eq!(values(&Field::parse("1,3,5-6", 0, 7).unwrap(), 0, 7), [1, 3, 5, 6]);
+        assert!(Field::parse("*", 1, 12).unwrap().is_any);
+        assert!(!Field::parse("1-12", 1, 12).unwrap().is_any);
+    }
+
+    #[test]
+    fn field_rejects_invalid_values() {
+        for text in ["60", "0", "5-2", "*/0", "a", "1,", "3-"] {
+            assert!(Field::parse(text, 1, 59).is_none(), "{} should be rejected", text);
+        }
+    }
+
+    #[test]
+    fn due_actions_catch_up_on_missed_minutes() {
+        let mut scheduler = Scheduler::new(&["* * * * * camera_start", "* * * * * preset door"]).unwrap();
+        let minute = scheduler.last_minute;
+
+        assert!(scheduler.due_actions_until(minute).is_empty());
+        assert_eq!(scheduler.due_actions_until(minute + 3), [Action::CameraStart(None), Action::Preset(String::from("door"))]);
+        assert!(scheduler.due_actions_until(minute + 3).is_empty());
+        assert!(scheduler.due_actions_until(minute + 2).is_empty());
+        assert_eq!(scheduler.due_actions_until(minute + 4).len(), 2);
+    }
+
+    #[test]
+    fn due_actions_evaluate_each_missed_minute() {
+        let mut scheduler = Scheduler::new::<&str>(&[]).unwrap();
+        let minute = scheduler.last_minute;
+        let missed = LocalTime::at_minute(minute + 2).minute;
+        scheduler.add(&format!("{} * * * *", missed), "snapshot").unwrap();
+
+        assert_eq!(scheduler.due_actions_until(minute + 1), []);
+        assert_eq!(scheduler.due_actions_until(minute + 5), [Action::Snapshot(None)]);
+    }
+
+    #[test]
+    fn schedule_requires_five_fields() {
+        assert!("* * * *".parse::<Schedule>().is_err());
+        assert!("* * * * * *".parse::<Schedule>().is_err());
+        assert!("0 7 * * 1-5".parse::<Schedule>().is_ok());
+    }
+
+    #[test]
+    fn schedule_matches_time_fields() {
+        let schedule = "*/30 7-9 * 6 *".parse::<Schedule>().unwrap();
+        assert!(schedule.matches(&time(30, 8, 14, 6, 3)));
+        assert!(!schedule.matches(&time(15, 8, 14, 6, 3)));
+        assert!(!schedule.matches(&time(30, 10, 14, 6, 3)));
+        assert!(!schedule.matches(&time(30, 8, 14, 7, 3)));
+    }
+
+    #[test]
+    fn schedule_treats_sunday_as_zero_or_seven() {
+        let schedule = "0 12 * * 7".parse::<Schedule>().unwrap();
+        assert!(schedule.matches(&time(0, 12, 4, 1, 0)));
+        assert!(!schedule.matches(&time(0, 12, 5, 1, 1)));
+    }
+
+    #[test]
+    fn schedule_matches_day_of_month_or_day_of_week() {
+        let both = "0 0 1,15 * 1".parse::<Schedule>().unwrap();
+        assert!(both.matches(&time(0, 0, 15, 3, 4)));
+        assert!(both.matches(&time(0, 0, 9, 3, 1)));
+        assert!(!both.matches(&time(0, 0, 9, 3, 4)));
+
+        let day_only = "0 0 1,15 * *".parse::<Schedule>().unwrap();
+        assert!(day_only.matches(&time(0, 0, 15, 3, 4)));
+        assert!(!day_only.matches(&time(0, 0, 9, 3, 1)));
+
+        let weekday_only = "0 0 * * 1".parse::<Schedule>().unwrap();
+        assert!(weekday_only.matches(&time(0, 0, 9, 3, 1)));
+        assert!(!weekday_only.matches(&time(0, 0, 15, 3, 4)));
+    }
+}
@@ -1,13 +1,39 @@
 use prost::Message;
-use std::collections::HashMap;
-use tokio::io::{AsyncRead, AsyncReadExt};
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
 use tokio::net::tcp::OwnedWriteHalf;
-use tokio::sync::mpsc::Sender;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc::{Receiver, Sender};
 
-use crate::{camera, servo::Servo, networking};
+use crate::{camera, diagnostics, fs, history::{self, History}, metrics, patrol::Patrol, scheduler::{self, Action, Scheduler}, servo::Servo, networking, recordings, settings::Settings, stream_proxy::StreamProxy, tracking::Tracker};
 use networking::MessageType;
 
-use self::messages::{HelloRequest, ServoRotateRequest};
+use self::messages::{
+    HelloRequest,
+    ServoRotateRequest,
+    CameraParameterGetRequest,
+    CameraParameterSetRequest,
+    DetectionSetRequest,
+    DetectionStatusRequest,
+    SnapshotRequest,
+    RecordingListRequest,
+    RecordingDownloadRequest,
+    RecordingDeleteRequest,
+    CameraStatusRequest,
+    ServoPositionRequest,
+    ServoVelocityRequest,
+    PatrolStartRequest,
+    PatrolStopRequest,
+    PatrolStatusRequest,
+    ScheduleListRequest,
+    ScheduleAddRequest,
+    ScheduleRemoveRequest,
+    EventHistoryRequest,
+    DiagnosticsRequest,
+};
 
 macro_rules! on_message {
     ($t:expr, $s:expr, {$($p:ident => $f:ident),+}) => {
@@ -16,8 +42,16 @@ macro_rules! on_message {
                 MessageType::$p => {
                     let sender_id = $t.sender_id;
                     let mut cursor = std::io::Cursor::new($t.payload);
-                    if let Ok(request) = $p::decode(&mut cursor) {
-                        $f(sender_id, request, $s).await;
+                    match $p::decode(&mut cursor) {
+                        Ok(request) => {
+                            metrics::message_handled(stringify!($p));
+                            $f(sender_id, request, $s).await;
+                            debug!(client = sender_id, message = stringify!($p), latency_us = $t.received_at.elapsed().as_micros(); "Handled message");
+                        },
+                        Err(e) => {
+                            metrics::decode_failed(stringify!($p));
+                            warn!(client = sender_id, message = stringify!($p); "Failed to decode message: {}", e);
+                        },
                     }
                 },
             )+
@@ -30,16 +64,27 @@ pub mod messages {
     include!(concat!(env!("OUT_DIR"), "/messages.rs"));
 }
 
+const CONTROL_PORT: u16 = 6688;
+const RECORDING_CHUNK_SIZE: usize = 64 * 1024;
+const MAX_HISTORY_EVENTS: usize = 1000;
+const CAMERA_STATE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const SERVO_PARK_TIMEOUT: Duration = Duration::from_secs(10);
+const CAMERA_STOP_TIMEOUT: Duration = Duration::from_secs(10);
+const CAMERA_STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 mod features {
     pub const CAMERA: u32 = 1 << 0;
     pub const SERVO: u32 = 1 << 1; 
 }
 
+type Connection = Arc<tokio::sync::Mutex<OwnedWriteHalf>>;
+
 #[derive(Debug, Clone)]
 enum Event {
     Connected,
-    Disconnected,
-    MessageReceived(ReceivedMessage)
+    Disconnected(u32),
+    MessageReceived(ReceivedMessage),
+    CameraParameterChanged(u32, u32, messages::CameraParameter, i32),
 }
 
 enum ReadState {
@@ -52,57 +97,144 @@ enum ReadState {
 struct ReceivedMessage {
     sender_id: u32,
     msg_type: MessageType,
-    payload: Vec<u8>
+    payload: Vec<u8>,
+    received_at: Instant,
 }
 
 pub struct Server {
-    camera: Box<dyn camera::Camera>,
+    settings: Settings,
+    cameras: Vec<camera::CameraInstance>,
     servo: Option<Servo>,
-    client_connections: HashMap<u32, OwnedWriteHalf>
+    patrol: Patrol,
+    scheduler: Scheduler,
+    tracker: Tracker,
+    history: History,
+    disabled_cameras: HashSet<u32>,
+    scheduled_cameras: HashSet<u32>,
+    camera_states: HashMap<u32, camera::State>,
+    driving_clients: HashSet<(u32, u32)>,
+    motion_events: Option<Receiver<messages::MotionEvent>>,
+    stream_proxies: HashMap<u32, StreamProxy>,
+    client_connections: HashMap<u32, Connection>,
+    events: Sender<Event>,
+    event_receiver: Option<Receiver<Event>>,
+    client_addresses: HashMap<u32, SocketAddr>,
+}
+
+pub struct Services {
+    pub patrol: Patrol,
+    pub scheduler: Scheduler,
+    pub tracker: Tracker,
+    pub history: History,
+    pub motion_events: Option<Receiver<messages::MotionEvent>>,
 }
 
 impl Server {
 
-    pub fn new(camera: Box<dyn camera::Camera>, servo: Option<Servo>) -> Self {
-        Server { camera, servo, client_connections: HashMap::new() }
+    pub fn new(settings: Settings, cameras: Vec<camera::CameraInstance>, servo: Option<Servo>, services: Services) -> Self {
+        let Services { patrol, scheduler, tracker, history, motion_events } = services;
+        let (events, event_receiver) = tokio::sync::mpsc::channel(10);
+        Server {
+            settings,
+            cameras,
+            servo,
+            patrol,
+            scheduler,
+            tracker,
+            history,
+            disabled_cameras: HashSet::new(),
+            scheduled_cameras: HashSet::new(),
+            camera_states: HashMap::new(),
+            driving_clients: HashSet::new(),
+            motion_events,
+            stream_proxies: HashMap::new(),
+            client_connections: HashMap::new(),
+            events,
+            event_receiver: Some(event_receiver),
+            client_addresses: HashMap::new(),
+        }
     }
     
     pub async fn start(mut self) -> Result<(), std::io::Error> {
-        println!("Features: {}", get_feature_set());
-        let listener = tokio::net::TcpListener::bind("0.0.0.0:6688").await.unwrap();
-        let (tx, mut rx) = tokio::sync::mpsc::channel(10);
+        info!("Features: {}", get_feature_set(&self));
+        let listener = tokio::net::TcpListener::bind(("0.0.0.0", CONTROL_PORT)).await.unwrap();
+        let proxy_ports = self.settings.cameras()?.into_iter()
+            .map(|camera| (camera.id, camera.stream_proxy_port))
+            .collect::<HashMap<_, _>>();
+        for instance in &self.cameras {
+            if instance.camera.stream_url().is_some() {
+                continue;
+            }
+
+            let port = match proxy_ports.get(&instance.id) {
+                Some(port) => *port,
+                None => continue,
+            };
+            match StreamProxy::start(port, instance.camera.port()).await {
+                Ok(proxy) => { self.stream_proxies.insert(instance.id, proxy); },
+                Err(e) => error!("Failed to start stream proxy for camera {}: {}", instance.id, e),
+            }
+        }
+
+        self.camera_states = self.cameras.iter()
+            .map(|instance| (instance.id, instance.camera.status().state))
+            .collect();
+        let mut camera_state_poll = tokio::time::interval(CAMERA_STATE_POLL_INTERVAL);
+        let schedule_tick = tokio::time::sleep(scheduler::until_next_minute());
+        tokio::pin!(schedule_tick);
+
+        let mut rx = self.event_receiver.take()
+            .ok_or_else(|| std::io::Error::other("Server is already running"))?;
         let mut currently_connected = 0_u32;
         let mut current_client_id = 0_u32;
+        let mut interrupt = signal(SignalKind::interrupt())?;
+        let mut terminate = signal(SignalKind::terminate())?;
 
-        loop {
+        let reason = loop {
             tokio::select! {
-                accept_result = listener.accept() => if let Ok((stream, _)) = accept_result {
+                accept_result = listener.accept() => if let Ok((stream, address)) = accept_result {
                     let (reader, writer) = stream.into_split();
-                    self.client_connections.insert(current_client_id, writer);
+                    self.client_connections.insert(current_client_id, Arc::new(tokio::sync::Mutex::new(writer)));
+                    self.client_addresses.insert(current_client_id, address);
+                    info!(client = current_client_id, address = address; "Client connected");
+                    metrics::client_connected();
+                    self.history.record(history::Kind::ClientConnected, &client_source(&self, current_client_id), "");
                     
-                    let sender = tx.clone();
+                    let sender = self.events.clone();
                     tokio::spawn(async move { handle_client_connection(reader, sender, current_client_id).await });
                     current_client_id += 1;
                 },
 
                 receive_result = rx.recv() => match receive_result {
                     Some(Event::Connected) => {
-                        println!("Client connected");
-                        if currently_connected == 0 && !self.camera.is_active() {
-                            println!("Enabling camera");
-                            if let Err(e) = self.camera.start() {
-                                eprintln!("Failed to start camera: {}", e);
-                            };
-                        } 
+                        if currently_connected == 0 {
+                            let disabled_cameras = &self.disabled_cameras;
+                            let idle_cameras = self.cameras.iter()
+                                .filter(|i| !i.camera.status().state.is_active() && !disabled_cameras.contains(&i.id));
+                            for instance in idle_cameras {
+                                info!(camera = instance.id; "Enabling camera");
+                                if let Err(e) = instance.camera.start().await {
+                                    error!(camera = instance.id; "Failed to start camera: {}", e);
+                                }
+                            }
+                        }
 
                         currently_connected += 1;
                     },
-                    Some(Event::Disconnected) => {
-                        println!("Client disconnected");
-                        if currently_connected == 1 && self.camera.is_active() {
-                            println!("Disabling camera");
-                            if let Err(e) = self.camera.stop() {
-                                eprintln!("Failed to stop camera: {}", e);
+                    Some(Event::Disconnected(client_id)) => {
+                        info!(client = client_id; "Client disconnected");
+                        metrics::client_disconnected();
+                        self.history.record(history::Kind::ClientDisconnected, &client_source(&self, client_id), "");
+                        self.client_addresses.remove(&client_id);
+                        self.client_connections.remove(&client_id);
+                        self.driving_clients.retain(|(id, _)| *id != client_id);
+                        if currently_connected == 1 {
+                            let scheduled_cameras = &self.scheduled_cameras;
+                            for instance in self.cameras.iter().filter(|i| !scheduled_cameras.contains(&i.id)) {
+                                info!(camera = instance.id; "Disabling camera");
+                                if let Err(e) = instance.camera.stop().await {
+                                    error!(camera = instance.id; "Failed to stop camera: {}", e);
+                                }
                             }
                             currently_connected = 0;
                         } else if currently_connected != 0 {
@@ -110,34 +242,646 @@ impl Server {
                         }
                     },
                     Some(Event::MessageReceived(message_data)) => {
-                        println!("Received {:?} from {}", message_data.msg_type, message_data.sender_id);
                         on_message!(message_data, &mut self, {
                             HelloRequest => on_hello_request,
-                            ServoRotateRequest => on_servo_rotate_request
+                            ServoRotateRequest => on_servo_rotate_request,
+                            CameraParameterGetRequest => on_camera_parameter_get_request,
+                            CameraParameterSetRequest => on_camera_parameter_set_request,
+                            DetectionSetRequest => on_detection_set_request,
+                            DetectionStatusRequest => on_detection_status_request,
+                            SnapshotRequest => on_snapshot_request,
+                            RecordingListRequest => on_recording_list_request,
+                            RecordingDownloadRequest => on_recording_download_request,
+                            RecordingDeleteRequest => on_recording_delete_request,
+                            CameraStatusRequest => on_camera_status_request,
+                            ServoPositionRequest => on_servo_position_request,
+                            ServoVelocityRequest => on_servo_velocity_request,
+                            PatrolStartRequest => on_patrol_start_request,
+                            PatrolStopRequest => on_patrol_stop_request,
+                            PatrolStatusRequest => on_patrol_status_request,
+                            ScheduleListRequest => on_schedule_list_request,
+                            ScheduleAddRequest => on_schedule_add_request,
+                            ScheduleRemoveRequest => on_schedule_remove_request,
+                            EventHistoryRequest => on_event_history_request,
+                            DiagnosticsRequest => on_diagnostics_request
                         });
                     }
+                    Some(Event::CameraParameterChanged(client_id, camera_id, parameter, value)) => {
+                        on_camera_parameter_changed(client_id, camera_id, parameter, value, &mut self).await;
+                    },
                     _ => {}
-                }
+                },
+
+                Some(event) = next_motion_event(&mut self.motion_events) => on_motion_event(event, &mut self).await,
+
+                _ = camera_state_poll.tick() => record_camera_states(&mut self),
+
+                () = &mut schedule_tick => {
+                    schedule_tick.as_mut().reset(tokio::time::Instant::now() + scheduler::until_next_minute());
+                    for action in self.scheduler.due_actions() {
+                        info!("Running scheduled action {}", action);
+                        if matches!(action, Action::Preset(_) | Action::Patrol(_) | Action::PatrolStop(_)) {
+                            self.history.record(history::Kind::ServoMoved, "schedule", &action.to_string());
+                        }
+                        if let Err(e) = run_scheduled_action(&action, &mut self).await {
+                            error!("Scheduled action {} failed: {}", action, e);
+                        }
+                    }
+                },
+
+                _ = interrupt.recv() => break "SIGINT",
+
+                _ = terminate.recv() => break "SIGTERM",
             }
+        };
+
+        drop(listener);
+        self.shutdown(reason).await;
+        Ok(())
+    }
+
+    async fn shutdown(&mut self, reason: &str) {
+        info!("Received {}, shutting down", reason);
+        for (client_id, connection) in std::mem::take(&mut self.client_connections) {
+            let mut connection = connection.lock().await;
+            let message = messages::ServerShutdown { reason: format!("Server received {}", reason) };
+            networking::send_message(&mut *connection, MessageType::ServerShutdown, message).await.unwrap_or_default();
+            connection.shutdown().await.unwrap_or_default();
+            self.history.record(history::Kind::ClientDisconnected, &client_source(self, client_id), "server shutdown");
+        }
+
+        self.patrol.stop("").unwrap_or_default();
+        if let Some(servo) = &self.servo {
+            match tokio::time::timeout(SERVO_PARK_TIMEOUT, servo.park()).await {
+                Ok(Ok(())) => {},
+                Ok(Err(e)) => error!("Failed to park servo: {}", e),
+                Err(_) => error!("Servo did not park in time"),
+            }
+        }
+
+        for instance in &self.cameras {
+            if let Err(e) = instance.camera.stop().await {
+                error!(camera = instance.id; "Failed to stop camera: {}", e);
+            }
+        }
+
+        let deadline = tokio::time::Instant::now() + CAMERA_STOP_TIMEOUT;
+        let is_stopped = |instance: &camera::CameraInstance| matches!(instance.camera.status().state, camera::State::Stopped | camera::State::Failed(_));
+        while !self.cameras.iter().all(is_stopped) {
+            if tokio::time::Instant::now() >= deadline {
+                error!("Cameras did not stop in time");
+                break;
+            }
+            tokio::time::sleep(CAMERA_STOP_POLL_INTERVAL).await;
         }
     }
 }
 
 async fn on_hello_request(sender_id: u32, _request: HelloRequest, server: &mut Server) {
-    if let Some(connection) = server.client_connections.get_mut(&sender_id) {
-        let camera_port = server.camera.port();
+    let cameras = server.cameras.iter()
+        .map(|instance| messages::CameraInfo {
+            id: instance.id,
+            stream_port: stream_port(server, instance) as i32,
+            stream_url: stream_url(server, instance),
+            has_rig: instance.rig.is_some(),
+            rig_id: instance.rig.unwrap_or_default(),
+        })
+        .collect::<Vec<_>>();
+    let rigs = server.servo.iter()
+        .flat_map(|servo| servo.rigs())
+        .map(|(rig, angles)| messages::RigInfo {
+            id: rig.id,
+            has_pan: rig.has_pan,
+            has_tilt: rig.has_tilt,
+            pan: angles.pan as u32,
+            tilt: angles.tilt as u32,
+        })
+        .collect();
+    let feature_set = get_feature_set(server);
+
+    if let Some(connection) = server.client_connections.get(&sender_id) {
         let message = messages::HelloResponse { 
             stream_host: crate::get_current_ip_address().to_string(),
-            stream_port: camera_port as i32,
-            feature_set: get_feature_set(),
+            stream_port: cameras.first().map_or(0, |c| c.stream_port),
+            feature_set,
+            stream_url: cameras.first().map(|c| c.stream_url.clone()).unwrap_or_default(),
+            cameras,
+            rigs,
         };
-        networking::send_message(connection, MessageType::HelloResponse, message).await.unwrap_or_default();
+        networking::send_message(&mut *connection.lock().await, MessageType::HelloResponse, message).await.unwrap_or_default();
     }
 }
 
-async fn on_servo_rotate_request(_sender_id: u32, request: ServoRotateRequest, server: &mut Server) {
+async fn on_servo_rotate_request(sender_id: u32, request: ServoRotateRequest, server: &mut Server) {
+    let details = format!("rig {} rotate {} {}", request.rig_id, request.dx, request.dy);
+    record_drive(sender_id, request.rig_id, request.dx != 0 || request.dy != 0, &details, server);
+    server.patrol.manual_control(request.rig_id);
     if let Some(s) = server.servo.as_mut() {
-        s.rotate(request.dx as i8, request.dy as i8);
+        let step = |value: i32| value.clamp(i8::MIN as i32, i8::MAX as i32) as i8;
+        if let Err(e) = s.rotate(request.rig_id, step(request.dx), step(request.dy)) {
+            error!(client = sender_id; "Failed to rotate servo rig {}: {}", request.rig_id, e);
+        }
+    }
+}
+
+async fn on_servo_position_request(sender_id: u32, request: ServoPositionRequest, server: &mut Server) {
+    let angle = |value: i32| (value >= 0).then(|| value.min(u8::MAX as i32) as f32);
+    let details = format!("rig {} position {} {}", request.rig_id, request.pan, request.tilt);
+    server.history.record(history::Kind::ServoMoved, &client_source(server, sender_id), &details);
+    server.patrol.manual_control(request.rig_id);
+    if let Some(s) = server.servo.as_mut() {
+        if let Err(e) = s.set_position(request.rig_id, angle(request.pan), angle(request.tilt)) {
+            error!(client = sender_id; "Failed to position servo rig {}: {}", request.rig_id, e);
+        }
+    }
+}
+
+async fn on_servo_velocity_request(sender_id: u32, request: ServoVelocityRequest, server: &mut Server) {
+    let details = format!("rig {} velocity {} {}", request.rig_id, request.pan, request.tilt);
+    record_drive(sender_id, request.rig_id, request.pan != 0.0 || request.tilt != 0.0, &details, server);
+    server.patrol.manual_control(request.rig_id);
+    if let Some(s) = server.servo.as_mut() {
+        if let Err(e) = s.drive(request.rig_id, request.pan, request.tilt) {
+            error!(client = sender_id; "Failed to drive servo rig {}: {}", request.rig_id, e);
+        }
+    }
+}
+
+async fn on_patrol_start_request(sender_id: u32, request: PatrolStartRequest, server: &mut Server) {
+    let result = server.patrol.start(&request.tour);
+    match &result {
+        Ok(_) => server.history.record(history::Kind::ServoMoved, &client_source(server, sender_id), &format!("patrol {}", request.tour)),
+        Err(e) => error!(client = sender_id; "Failed to start patrol {}: {}", request.tour, e),
+    }
+
+    send_patrol_status_response(sender_id, result.is_ok(), server).await;
+}
+
+async fn on_patrol_stop_request(sender_id: u32, request: PatrolStopRequest, server: &mut Server) {
+    let result = server.patrol.stop(&request.tour);
+    match &result {
+        Ok(_) => server.history.record(history::Kind::ServoMoved, &client_source(server, sender_id), &format!("patrol_stop {}", request.tour)),
+        Err(e) => error!(client = sender_id; "Failed to stop patrol {}: {}", request.tour, e),
+    }
+
+    send_patrol_status_response(sender_id, result.is_ok(), server).await;
+}
+
+async fn on_patrol_status_request(sender_id: u32, _request: PatrolStatusRequest, server: &mut Server) {
+    send_patrol_status_response(sender_id, true, server).await;
+}
+
+async fn send_patrol_status_response(sender_id: u32, success: bool, server: &mut Server) {
+    let tours = server.patrol.tours()
+        .map(|status| messages::PatrolTourInfo {
+            name: status.tour.name.clone(),
+            rig_id: status.tour.rig,
+            waypoint_count: status.tour.waypoints.len() as u32,
+            active: status.is_active,
+            paused: status.is_paused,
+        })
+        .collect();
+
+    if let Some(connection) = server.client_connections.get(&sender_id) {
+        let message = messages::PatrolStatusResponse { success, tours };
+        networking::send_message(&mut *connection.lock().await, MessageType::PatrolStatusResponse, message).await.unwrap_or_default();
+    }
+}
+
+async fn on_schedule_list_request(sender_id: u32, _request: ScheduleListRequest, server: &mut Server) {
+    send_schedule_list_response(sender_id, true, server).await;
+}
+
+async fn on_schedule_add_request(sender_id: u32, request: ScheduleAddRequest, server: &mut Server) {
+    let result = server.scheduler.add(&request.schedule, &request.action).and_then(|id| {
+        let saved = server.settings.set_schedule(&server.scheduler.lines());
+        if saved.is_err() {
+            server.scheduler.remove(id).unwrap_or_default();
+        }
+        saved
+    });
+    if let Err(e) = &result {
+        error!(client = sender_id; "Failed to add schedule entry: {}", e);
+    }
+
+    send_schedule_list_response(sender_id, result.is_ok(), server).await;
+}
+
+async fn on_schedule_remove_request(sender_id: u32, request: ScheduleRemoveRequest, server: &mut Server) {
+    let result = server.scheduler.remove(request.id)
+        .and_then(|_| server.settings.set_schedule(&server.scheduler.lines()));
+    if let Err(e) = &result {
+        error!(client = sender_id; "Failed to remove schedule entry {}: {}", request.id, e);
+    }
+
+    send_schedule_list_response(sender_id, result.is_ok(), server).await;
+}
+
+async fn send_schedule_list_response(sender_id: u32, success: bool, server: &mut Server) {
+    let entries = server.scheduler.entries().iter()
+        .map(|entry| messages::ScheduleEntry {
+            id: entry.id,
+            schedule: entry.schedule.to_string(),
+            action: entry.action.to_string(),
+        })
+        .collect();
+
+    if let Some(connection) = server.client_connections.get(&sender_id) {
+        let message = messages::ScheduleListResponse { success, entries };
+        networking::send_message(&mut *connection.lock().await, MessageType::ScheduleListResponse, message).await.unwrap_or_default();
+    }
+}
+
+async fn on_event_history_request(sender_id: u32, request: EventHistoryRequest, server: &mut Server) {
+    let kinds = request.kinds().map(history_kind).collect::<Vec<_>>();
+    let to = (request.to != 0).then_some(request.to);
+    let limit = match request.limit {
+        0 => MAX_HISTORY_EVENTS,
+        limit => (limit as usize).min(MAX_HISTORY_EVENTS),
+    };
+
+    let result = server.history.query(request.from, to, &kinds, limit);
+    if let Err(e) = &result {
+        error!(client = sender_id; "Failed to read event history: {}", e);
+    }
+
+    if let Some(connection) = server.client_connections.get(&sender_id) {
+        let mut message = messages::EventHistoryResponse { success: result.is_ok(), ..Default::default() };
+        for record in result.unwrap_or_default() {
+            let mut event = messages::HistoryEvent {
+                timestamp: record.timestamp,
+                source: record.source,
+                details: record.details,
+                ..Default::default()
+            };
+            event.set_kind(history_event_kind(record.kind));
+            message.events.push(event);
+        }
+        networking::send_message(&mut *connection.lock().await, MessageType::EventHistoryResponse, message).await.unwrap_or_default();
+    }
+}
+
+async fn on_diagnostics_request(sender_id: u32, _request: DiagnosticsRequest, server: &mut Server) {
+    let uses_motion = server.cameras.iter().any(|instance| instance.backend == "motion");
+    let mut stream_ports = Vec::new();
+    for instance in server.cameras.iter().filter(|instance| instance.camera.stream_url().is_none()) {
+        stream_ports.push(diagnostics::StreamPort { camera_id: instance.id, name: "stream", port: instance.camera.port() });
+        if let Some(proxy) = server.stream_proxies.get(&instance.id) {
+            stream_ports.push(diagnostics::StreamPort { camera_id: instance.id, name: "stream proxy", port: proxy.port() });
+        }
+    }
+
+    let checks = match fs::Fs::new() {
+        Ok(fs) => diagnostics::run(&fs, &server.settings, uses_motion, &stream_ports).await,
+        Err(e) => {
+            error!(client = sender_id; "Failed to run diagnostics: {}", e);
+            Vec::new()
+        },
+    };
+
+    if let Some(connection) = server.client_connections.get(&sender_id) {
+        let message = messages::DiagnosticsResponse {
+            success: !checks.is_empty() && checks.iter().all(|check| check.success),
+            checks: checks.into_iter()
+                .map(|check| messages::DiagnosticCheck { name: check.name, success: check.success, details: check.details })
+                .collect(),
+        };
+        networking::send_message(&mut *connection.lock().await, MessageType::DiagnosticsResponse, message).await.unwrap_or_default();
+    }
+}
+
+fn record_drive(sender_id: u32, rig: u32, is_moving: bool, details: &str, server: &mut Server) {
+    if !is_moving {
+        server.driving_clients.remove(&(sender_id, rig));
+    } else if server.driving_clients.insert((sender_id, rig)) {
+        server.history.record(history::Kind::ServoMoved, &client_source(server, sender_id), details);
+    }
+}
+
+fn record_camera_states(server: &mut Server) {
+    for instance in &server.cameras {
+        let state = instance.camera.status().state;
+        metrics::camera_state(instance.id, &state);
+        if server.camera_states.get(&instance.id) != Some(&state) {
+            server.history.record(history::Kind::CameraStateChanged, &format!("camera {}", instance.id), &format!("{:?}", state));
+            server.camera_states.insert(instance.id, state);
+        }
+    }
+}
+
+fn client_source(server: &Server, client_id: u32) -> String {
+    match server.client_addresses.get(&client_id) {
+        Some(address) => format!("client {} ({})", client_id, address.ip()),
+        None => format!("client {}", client_id),
+    }
+}
+
+fn history_kind(kind: messages::HistoryEventKind) -> history::Kind {
+    match kind {
+        messages::HistoryEventKind::ClientConnected => history::Kind::ClientConnected,
+        messages::HistoryEventKind::ClientDisconnected => history::Kind::ClientDisconnected,
+        messages::HistoryEventKind::ServoMoved => history::Kind::ServoMoved,
+        messages::HistoryEventKind::CameraStateChanged => history::Kind::CameraStateChanged,
+        messages::HistoryEventKind::Motion => history::Kind::Motion,
+        messages::HistoryEventKind::AuthFailure => history::Kind::AuthFailure,
+    }
+}
+
+fn history_event_kind(kind: history::Kind) -> messages::HistoryEventKind {
+    match kind {
+        history::Kind::ClientConnected => messages::HistoryEventKind::ClientConnected,
+        history::Kind::ClientDisconnected => messages::HistoryEventKind::ClientDisconnected,
+        history::Kind::ServoMoved => messages::HistoryEventKind::ServoMoved,
+        history::Kind::CameraStateChanged => messages::HistoryEventKind::CameraStateChanged,
+        history::Kind::Motion => messages::HistoryEventKind::Motion,
+        history::Kind::AuthFailure => messages::HistoryEventKind::AuthFailure,
+    }
+}
+
+async fn next_motion_event(events: &mut Option<Receiver<messages::MotionEvent>>) -> Option<messages::MotionEvent> {
+    match events {
+        Some(events) => events.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn on_motion_event(mut event: messages::MotionEvent, server: &mut Server) {
+    if event.kind() == messages::MotionEventKind::MotionDetected {
+        if let Some(rig) = server.tracker.rig(event.camera_id) {
+            server.patrol.manual_control(rig);
+            server.tracker.report(event.camera_id, event.x, event.y);
+        }
+        return;
+    }
+
+    info!(camera = event.camera_id, event = event.event_id; "Motion event {:?}", event.kind());
+    let details = format!("{:?} event {} {}", event.kind(), event.event_id, event.file);
+    server.history.record(history::Kind::Motion, &format!("camera {}", event.camera_id), details.trim_end());
+    if event.timestamp == 0 {
+        event.timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    }
+
+    let mut failed_clients = Vec::new();
+    for (client_id, connection) in server.client_connections.iter() {
+        if let Err(e) = networking::send_message(&mut *connection.lock().await, MessageType::MotionEvent, event.clone()).await {
+            debug!(client = *client_id; "Dropping connection after failing to send motion event: {}", e);
+            failed_clients.push(*client_id);
+        }
+    }
+    for client_id in failed_clients {
+        server.client_connections.remove(&client_id);
+    }
+}
+
+async fn run_scheduled_action(action: &Action, server: &mut Server) -> std::io::Result<()> {
+    let camera_ids = |camera: &Option<u32>| match camera {
+        Some(id) => find_camera(server, *id).map(|instance| vec![instance.id]),
+        None => Ok(server.cameras.iter().map(|instance| instance.id).collect()),
+    };
+
+    match action {
+        Action::CameraStart(camera) => {
+            for id in camera_ids(camera)? {
+                server.disabled_cameras.remove(&id);
+                server.scheduled_cameras.insert(id);
+                find_camera(server, id)?.camera.start().await?;
+            }
+        },
+        Action::CameraStop(camera) => {
+            for id in camera_ids(camera)? {
+                server.disabled_cameras.insert(id);
+                server.scheduled_cameras.remove(&id);
+                find_camera(server, id)?.camera.stop().await?;
+            }
+        },
+        Action::Preset(name) => server.patrol.go_to_preset(name).map_err(|e| std::io::Error::other(e.to_string()))?,
+        Action::Patrol(name) => server.patrol.start(name).map_err(|e| std::io::Error::other(e.to_string()))?,
+        Action::PatrolStop(name) => server.patrol.stop(name.as_deref().unwrap_or_default()).map_err(|e| std::io::Error::other(e.to_string()))?,
+        Action::Snapshot(camera) => {
+            for id in camera_ids(camera)? {
+                let dir = recordings_dir(server, id)?;
+                let jpeg = find_camera(server, id)?.camera.snapshot().await?;
+                let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+                std::fs::write(dir.join(format!("snapshot-{}-{}.jpg", id, timestamp)), jpeg)?;
+            }
+        },
+    }
+
+    Ok(())
+}
+
+async fn on_camera_parameter_get_request(sender_id: u32, request: CameraParameterGetRequest, server: &mut Server) {
+    let parameter = request.parameter();
+    let camera = find_camera(server, request.camera_id).map(|instance| instance.camera.clone());
+    let connection = server.client_connections.get(&sender_id).cloned();
+    tokio::spawn(async move {
+        let result = async { camera?.get_parameter(camera_parameter(parameter)).await }.await;
+        if let Err(e) = &result {
+            error!(client = sender_id; "Failed to read camera {} parameter {:?}: {}", request.camera_id, parameter, e);
+        }
+
+        send_camera_parameter_response(connection, request.camera_id, parameter, result).await;
+    });
+}
+
+async fn on_camera_parameter_set_request(sender_id: u32, request: CameraParameterSetRequest, server: &mut Server) {
+    let parameter = request.parameter();
+    let camera = find_camera(server, request.camera_id).map(|instance| instance.camera.clone());
+    let connection = server.client_connections.get(&sender_id).cloned();
+    let events = server.events.clone();
+    tokio::spawn(async move {
+        match async { camera?.set_parameter(camera_parameter(parameter), request.value).await }.await {
+            Ok(()) => {
+                let event = Event::CameraParameterChanged(sender_id, request.camera_id, parameter, request.value);
+                events.send(event).await.unwrap_or_default();
+            },
+            Err(e) => {
+                error!(client = sender_id; "Failed to set camera {} parameter {:?}: {}", request.camera_id, parameter, e);
+                send_camera_parameter_response(connection, request.camera_id, parameter, Err(e)).await;
+            },
+        }
+    });
+}
+
+async fn on_camera_parameter_changed(sender_id: u32, camera_id: u32, parameter: messages::CameraParameter, value: i32, server: &mut Server) {
+    let result = server.settings.set_camera_parameter(camera_id, camera_parameter(parameter), value).map(|_| value);
+    if let Err(e) = &result {
+        error!(client = sender_id; "Failed to save camera {} parameter {:?}: {}", camera_id, parameter, e);
+    }
+
+    send_camera_parameter_response(server.client_connections.get(&sender_id).cloned(), camera_id, parameter, result).await;
+}
+
+async fn send_camera_parameter_response(connection: Option<Connection>, camera_id: u32, parameter: messages::CameraParameter, result: std::io::Result<i32>) {
+    if let Some(connection) = connection {
+        let mut message = messages::CameraParameterResponse {
+            value: *result.as_ref().unwrap_or(&0),
+            success: result.is_ok(),
+            camera_id,
+            ..Default::default()
+        };
+        message.set_parameter(parameter);
+        networking::send_message(&mut *connection.lock().await, MessageType::CameraParameterResponse, message).await.unwrap_or_default();
+    }
+}
+
+async fn on_detection_set_request(sender_id: u32, request: DetectionSetRequest, server: &mut Server) {
+    let result = async { find_camera(server, request.camera_id)?.camera.set_detection_enabled(request.enabled).await }.await;
+    if let Err(e) = result {
+        error!(client = sender_id; "Failed to switch motion detection of camera {}: {}", request.camera_id, e);
+    }
+
+    on_detection_status_request(sender_id, DetectionStatusRequest { camera_id: request.camera_id }, server).await;
+}
+
+async fn on_detection_status_request(sender_id: u32, request: DetectionStatusRequest, server: &mut Server) {
+    let result = async { find_camera(server, request.camera_id)?.camera.is_detection_enabled().await }.await;
+    if let Some(connection) = server.client_connections.get(&sender_id) {
+        let message = messages::DetectionStatusResponse {
+            enabled: *result.as_ref().unwrap_or(&false),
+            success: result.is_ok(),
+            camera_id: request.camera_id,
+        };
+        networking::send_message(&mut *connection.lock().await, MessageType::DetectionStatusResponse, message).await.unwrap_or_default();
+    }
+}
+
+async fn on_snapshot_request(sender_id: u32, request: SnapshotRequest, server: &mut Server) {
+    let camera = find_camera(server, request.camera_id).map(|instance| instance.camera.clone());
+    let connection = server.client_connections.get(&sender_id).cloned();
+    tokio::spawn(async move {
+        let result = async { camera?.snapshot().await }.await;
+        if let Err(e) = &result {
+            error!(client = sender_id; "Failed to take a snapshot with camera {}: {}", request.camera_id, e);
+        }
+
+        if let Some(connection) = connection {
+            let message = messages::SnapshotResponse {
+                success: result.is_ok(),
+                image: result.unwrap_or_default(),
+                camera_id: request.camera_id,
+            };
+            networking::send_message(&mut *connection.lock().await, MessageType::SnapshotResponse, message).await.unwrap_or_default();
+        }
+    });
+}
+
+async fn on_recording_list_request(sender_id: u32, request: RecordingListRequest, server: &mut Server) {
+    let result = recordings_dir(server, request.camera_id).and_then(|dir| recordings::list(&dir));
+    if let Err(e) = &result {
+        error!(client = sender_id; "Failed to list recordings: {}", e);
+    }
+
+    if let Some(connection) = server.client_connections.get(&sender_id) {
+        let message = messages::RecordingListResponse {
+            success: result.is_ok(),
+            recordings: result.unwrap_or_default()
+                .into_iter()
+                .map(|r| messages::Recording {
+                    name: r.name,
+                    size: r.size,
+                    timestamp: r.timestamp,
+                    duration: r.duration,
+                })
+                .collect(),
+            camera_id: request.camera_id,
+        };
+        networking::send_message(&mut *connection.lock().await, MessageType::RecordingListResponse, message).await.unwrap_or_default();
+    }
+}
+
+async fn on_recording_download_request(sender_id: u32, request: RecordingDownloadRequest, server: &mut Server) {
+    let result = recordings_dir(server, request.camera_id)
+        .and_then(|dir| recordings::read_chunk(&dir, &request.name, request.offset, RECORDING_CHUNK_SIZE));
+    if let Err(e) = &result {
+        error!(client = sender_id; "Failed to read recording {}: {}", request.name, e);
+    }
+
+    if let Some(connection) = server.client_connections.get(&sender_id) {
+        let mut message = messages::RecordingChunk {
+            name: request.name,
+            offset: request.offset,
+            success: result.is_ok(),
+            camera_id: request.camera_id,
+            ..Default::default()
+        };
+        if let Ok(chunk) = result {
+            message.total_size = chunk.total_size;
+            message.data = chunk.data;
+        }
+        networking::send_message(&mut *connection.lock().await, MessageType::RecordingChunk, message).await.unwrap_or_default();
+    }
+}
+
+async fn on_recording_delete_request(sender_id: u32, request: RecordingDeleteRequest, server: &mut Server) {
+    let result = recordings_dir(server, request.camera_id).and_then(|dir| recordings::delete(&dir, &request.name));
+    match &result {
+        Ok(_) => info!(client = sender_id; "Deleted recording {}", request.name),
+        Err(e) => error!(client = sender_id; "Failed to delete recording {}: {}", request.name, e),
+    }
+
+    if let Some(connection) = server.client_connections.get(&sender_id) {
+        let message = messages::RecordingDeleteResponse {
+            name: request.name,
+            success: result.is_ok(),
+            camera_id: request.camera_id,
+        };
+        networking::send_message(&mut *connection.lock().await, MessageType::RecordingDeleteResponse, message).await.unwrap_or_default();
+    }
+}
+
+async fn on_camera_status_request(sender_id: u32, request: CameraStatusRequest, server: &mut Server) {
+    let result = find_camera(server, request.camera_id)
+        .map(|instance| (instance.camera.status(), stream_url(server, instance)));
+    if let Err(e) = &result {
+        error!(client = sender_id; "Failed to read camera status: {}", e);
+    }
+
+    if let Some(connection) = server.client_connections.get(&sender_id) {
+        let mut message = messages::CameraStatusResponse {
+            camera_id: request.camera_id,
+            success: result.is_ok(),
+            ..Default::default()
+        };
+
+        if let Ok((status, stream_url)) = result {
+            let (state, failure_reason) = match status.state {
+                camera::State::Stopped => (messages::CameraState::Stopped, String::new()),
+                camera::State::Starting => (messages::CameraState::Starting, String::new()),
+                camera::State::Running => (messages::CameraState::Running, String::new()),
+                camera::State::Stopping => (messages::CameraState::Stopping, String::new()),
+                camera::State::Failed(reason) => (messages::CameraState::Failed, reason),
+            };
+
+            message.set_state(state);
+            message.failure_reason = failure_reason;
+            message.width = status.width;
+            message.height = status.height;
+            message.framerate = status.framerate;
+            message.stream_url = stream_url;
+            message.capabilities = status.capabilities;
+        }
+
+        networking::send_message(&mut *connection.lock().await, MessageType::CameraStatusResponse, message).await.unwrap_or_default();
+    }
+}
+
+fn find_camera(server: &Server, camera_id: u32) -> std::io::Result<&camera::CameraInstance> {
+    server.cameras.iter()
+        .find(|instance| instance.id == camera_id)
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, format!("There is no camera {}", camera_id)))
+}
+
+fn recordings_dir(server: &Server, camera_id: u32) -> std::io::Result<std::path::PathBuf> {
+    find_camera(server, camera_id)?.camera.recordings_dir()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::Unsupported, format!("Camera {} does not keep recordings", camera_id)))
+}
+
+fn camera_parameter(parameter: messages::CameraParameter) -> camera::Parameter {
+    match parameter {
+        messages::CameraParameter::Framerate => camera::Parameter::Framerate,
+        messages::CameraParameter::Quality => camera::Parameter::Quality,
+        messages::CameraParameter::Threshold => camera::Parameter::Threshold,
     }
 }
 
@@ -157,21 +901,25 @@ async fn handle_client_connection<R>(reader: R, sender: Sender<Event>, client_id
         match current_state {
             ReadState::MsgType => {
                 let bytes_read = reader.read(&mut num_buffer).await?;
-                if disconnect_if_none_is_read(bytes_read, &sender).await { break; }
+                if disconnect_if_none_is_read(bytes_read, &sender, client_id).await { break; }
                 else if bytes_read < num_buffer.len() { continue; }
 
                 let message_type_index = u32::from_le_bytes(num_buffer);
 
                 match networking::msg_type_from_id(message_type_index as u32) {
                     Some(tt) => { current_message_type = tt },
-                    None => { eprintln!("Unrecognized message type index {}", message_type_index); break; },
+                    None => {
+                        metrics::decode_failed("unknown");
+                        warn!(client = client_id; "Unrecognized message type index {}", message_type_index);
+                        break;
+                    },
                 }
 
                 current_state = ReadState::Length;
             },
             ReadState::Length => {
                 let bytes_read = reader.read(&mut num_buffer).await?;
-                if disconnect_if_none_is_read(bytes_read, &sender).await { break; }
+                if disconnect_if_none_is_read(bytes_read, &sender, client_id).await { break; }
                 else if bytes_read < num_buffer.len() { continue; }
 
                 message_length = u32::from_le_bytes(num_buffer) as usize;
@@ -182,7 +930,8 @@ async fn handle_client_connection<R>(reader: R, sender: Sender<Event>, client_id
                     sender.send(Event::MessageReceived(ReceivedMessage {
                         sender_id: client_id,
                         msg_type: current_message_type,
-                        payload: buffer.clone()
+                        payload: buffer.clone(),
+                        received_at: Instant::now(),
                     }))
                     .await
                     .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
@@ -195,13 +944,14 @@ async fn handle_client_connection<R>(reader: R, sender: Sender<Event>, client_id
                 }
 
                 let bytes_read = reader.read(&mut buffer[0..message_length]).await?;
-                if disconnect_if_none_is_read(bytes_read, &sender).await { break; }
+                if disconnect_if_none_is_read(bytes_read, &sender, client_id).await { break; }
                 else if bytes_read < buffer.len() { continue; }
 
                 sender.send(Event::MessageReceived(ReceivedMessage {
                     sender_id: client_id,
                     msg_type: current_message_type,
-                    payload: buffer[0..message_length].to_owned() 
+                    payload: buffer[0..message_length].to_owned(),
+                    received_at: Instant::now(),
                 }))
                 .await
                 .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
@@ -211,9 +961,9 @@ async fn handle_client_connection<R>(reader: R, sender: Sender<Event>, client_id
             },
         }
 
-        async fn disconnect_if_none_is_read(bytes_read: usize, sender: &Sender<Event>) -> bool {
+        async fn disconnect_if_none_is_read(bytes_read: usize, sender: &Sender<Event>, client_id: u32) -> bool {
             if bytes_read == 0 {
-                sender.send(Event::Disconnected).await.unwrap_or_default();
+                sender.send(Event::Disconnected(client_id)).await.unwrap_or_default();
                 return true;
             }
 
@@ -225,11 +975,23 @@ async fn handle_client_connection<R>(reader: R, sender: Sender<Event>, client_id
     Ok(())
 }
 
-fn get_feature_set() -> u32 {
+fn stream_port(server: &Server, instance: &camera::CameraInstance) -> u16 {
+    match server.stream_proxies.get(&instance.id) {
+        Some(proxy) => proxy.port(),
+        None => instance.camera.port(),
+    }
+}
+
+fn stream_url(server: &Server, instance: &camera::CameraInstance) -> String {
+    instance.camera.stream_url().unwrap_or_else(||
+        format!("http://{}:{}/", crate::get_current_ip_address(), stream_port(server, instance))
+    )
+}
+
+fn get_feature_set(server: &Server) -> u32 {
     let mut features = features::CAMERA;
 
-    #[cfg(feature="servo")]
-    {
+    if server.servo.as_ref().is_some_and(|servo| !servo.is_simulated()) {
         features |= features::SERVO;
     }
 
@@ -1,83 +1,420 @@
-use std::{fmt::Display, time::Duration};
+use std::fmt::Display;
+use std::path::Path;
+use std::time::Duration;
 use tokio::sync::mpsc::{self, Sender, Receiver};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::oneshot;
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+use crate::metrics;
+use crate::settings::{AxisSettings, ParkSettings, RigSettings, ServoSettings};
 
 #[cfg(feature = "servo")]
 mod pca_servo;
 mod test_servo;
+mod planner;
 
 #[cfg(feature = "servo")]
 use pca_servo::Pca9685Servo;
+use planner::{AxisMotion, Limits};
 
-const SERVO_ROTATION_INTERVAL: u64 = 500;
+const SERVO_ROTATION_STEP_RATE: f32 = 2.0;
+const SERVO_COMMAND_BUFFER_SIZE: usize = 16;
 
 #[derive(Debug)]
 pub enum Error {
     ServoNotEnabled,
     DeviceNotAvailable,
     CommunicationFailure,
+    UnknownRig,
+    Busy,
 }
 
 trait ServoImpl {
-    fn rotate(&mut self, dx: i8, dy: i8);
+    fn set_angle(&mut self, board: u8, channel: u8, angle: f32) -> std::io::Result<()>;
+    fn sleep(&mut self) -> std::io::Result<()>;
+    fn wake(&mut self) -> std::io::Result<()>;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Angles {
+    pub pan: u8,
+    pub tilt: u8,
+}
+
+enum ServoControl {
+    Velocity { rig: u32, pan: f32, tilt: f32, timeout: Option<Duration> },
+    Position { rig: u32, pan: Option<f32>, tilt: Option<f32>, speed: Option<f32> },
+}
+
+pub struct BoardProbe {
+    pub mode: u8,
+    pub prescale: u8,
+    pub expected_prescale: u8,
 }
 
-struct ServoControl {
-    pub dx: i8,
-    pub dy: i8,
+#[derive(Clone, Debug)]
+pub struct RigInfo {
+    pub id: u32,
+    pub has_pan: bool,
+    pub has_tilt: bool,
 }
 
+#[derive(Clone)]
 pub struct Servo {
     sender: Sender<ServoControl>,
+    park_sender: Sender<oneshot::Sender<()>>,
+    rigs: Vec<(RigInfo, watch::Receiver<Angles>, watch::Receiver<bool>)>,
+    max_speed: f32,
+    deadman_timeout: Duration,
+    is_simulated: bool,
+}
+
+struct Rig {
+    id: u32,
+    pan: Option<Axis>,
+    tilt: Option<Axis>,
+    deadline: Option<Instant>,
+    angles: watch::Sender<Angles>,
+    moving: watch::Sender<bool>,
+}
+
+struct Axis {
+    settings: AxisSettings,
+    motion: AxisMotion,
 }
 
 #[cfg(feature = "servo")]
-pub fn init() -> Result<Servo, Error> {
-    // let r#impl = test_servo::TestServo;
-    let r#impl = Pca9685Servo::new()?;
-    Servo::new(r#impl)
+pub fn init(settings: &ServoSettings) -> Result<Servo, Error> {
+    let r#impl = Pca9685Servo::new(&settings.i2c_device, &settings.boards)?;
+    Servo::new(r#impl, settings)
 }
 
 #[cfg(not(feature = "servo"))]
-pub fn init() -> Result<Servo, Error> {
+pub fn init(_settings: &ServoSettings) -> Result<Servo, Error> {
     Err(Error::ServoNotEnabled)
 }
 
+#[cfg(feature = "servo")]
+pub fn probe_board(device: &Path, address: u16) -> std::io::Result<BoardProbe> {
+    pca_servo::probe(device, address)
+}
+
+#[cfg(not(feature = "servo"))]
+pub fn probe_board(_device: &Path, _address: u16) -> std::io::Result<BoardProbe> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, Error::ServoNotEnabled.to_string()))
+}
+
+pub fn simulated(settings: &ServoSettings) -> Result<Servo, Error> {
+    Servo::new(test_servo::TestServo::new(&settings.i2c_device, &settings.boards), settings)
+        .map(|servo| Servo { is_simulated: true, ..servo })
+}
+
 impl Servo {
-    fn new<T>(servo_impl: T) -> Result<Self, Error> where T: ServoImpl + Send + 'static {
-        let (sender, receiver) = mpsc::channel(1);
-        tokio::spawn(async move {
-            if let Err(e) = servo_control_routine(servo_impl, receiver).await {
-                eprintln!("Servo has failed: {}", e);
-            }
-        });
-        Ok(Servo { sender })
+    fn new<T>(mut servo_impl: T, settings: &ServoSettings) -> Result<Self, Error> where T: ServoImpl + Send + 'static {
+        let mut rigs = Vec::new();
+        let mut infos = Vec::new();
+        for rig_settings in &settings.rigs {
+            let rig = Rig::new(rig_settings, settings);
+            rig.apply(&mut servo_impl)?;
+
+            let info = RigInfo { id: rig.id, has_pan: rig.pan.is_some(), has_tilt: rig.tilt.is_some() };
+            infos.push((info, rig.angles.subscribe(), rig.moving.subscribe()));
+            rigs.push(rig);
+        }
+
+        let (sender, receiver) = mpsc::channel(SERVO_COMMAND_BUFFER_SIZE);
+        let (park_sender, park_receiver) = mpsc::channel(1);
+        tokio::spawn(servo_control_routine(servo_impl, rigs, settings.park.clone(), settings.idle_timeout, receiver, park_receiver));
+        Ok(Servo { sender, park_sender, rigs: infos, max_speed: settings.max_speed, deadman_timeout: settings.deadman_timeout, is_simulated: false })
+    }
+
+    pub fn is_simulated(&self) -> bool {
+        self.is_simulated
+    }
+
+    pub fn rigs(&self) -> impl Iterator<Item = (&RigInfo, Angles)> {
+        self.rigs.iter().map(|(info, angles, _)| (info, *angles.borrow()))
+    }
+
+    pub fn angles(&self, rig: u32) -> Option<watch::Receiver<Angles>> {
+        self.rigs.iter()
+            .find(|(info, _, _)| info.id == rig)
+            .map(|(_, angles, _)| angles.clone())
+    }
+
+    pub fn motion(&self, rig: u32) -> Option<watch::Receiver<bool>> {
+        self.rigs.iter()
+            .find(|(info, _, _)| info.id == rig)
+            .map(|(_, _, moving)| moving.clone())
+    }
+
+    pub fn rotate(&mut self, rig: u32, dx: i8, dy: i8) -> Result<(), Error> {
+        let (pan, tilt) = (dx as f32 * SERVO_ROTATION_STEP_RATE, dy as f32 * SERVO_ROTATION_STEP_RATE);
+        self.nudge(rig, pan, tilt)
+    }
+
+    pub fn set_velocity(&mut self, rig: u32, pan: f32, tilt: f32) -> Result<(), Error> {
+        self.send(rig, ServoControl::Velocity { rig, pan, tilt, timeout: None })
+    }
+
+    pub fn drive(&mut self, rig: u32, pan: f32, tilt: f32) -> Result<(), Error> {
+        let speed = |value: f32| if value.is_finite() { value.clamp(-1.0, 1.0) * self.max_speed } else { 0.0 };
+        let control = ServoControl::Velocity { rig, pan: speed(pan), tilt: speed(tilt), timeout: Some(self.deadman_timeout) };
+        self.send(rig, control)
+    }
+
+    pub fn nudge(&mut self, rig: u32, pan: f32, tilt: f32) -> Result<(), Error> {
+        self.send(rig, ServoControl::Velocity { rig, pan, tilt, timeout: Some(self.deadman_timeout) })
+    }
+
+    pub fn set_position(&mut self, rig: u32, pan: Option<f32>, tilt: Option<f32>) -> Result<(), Error> {
+        self.send(rig, ServoControl::Position { rig, pan, tilt, speed: None })
+    }
+
+    pub fn move_to(&mut self, rig: u32, pan: f32, tilt: f32, speed: Option<f32>) -> Result<(), Error> {
+        self.send(rig, ServoControl::Position { rig, pan: Some(pan), tilt: Some(tilt), speed })
+    }
+
+    pub async fn park(&self) -> Result<(), Error> {
+        let (done, parked) = oneshot::channel();
+        self.park_sender.send(done).await.map_err(|_| Error::CommunicationFailure)?;
+        parked.await.map_err(|_| Error::CommunicationFailure)
     }
 
-    pub fn rotate(&mut self, dx: i8, dy: i8) {
-        let sender = self.sender.clone();
-        tokio::spawn(async move {
-            sender.send(ServoControl { dx, dy }).await.unwrap_or_default();
-        });
+    fn send(&self, rig: u32, control: ServoControl) -> Result<(), Error> {
+        if !self.rigs.iter().any(|(info, _, _)| info.id == rig) {
+            return Err(Error::UnknownRig);
+        }
+
+        self.sender.try_send(control).map_err(|e| match e {
+            TrySendError::Full(_) => Error::Busy,
+            TrySendError::Closed(_) => Error::CommunicationFailure,
+        })
     }
 }
 
-async fn servo_control_routine<T>(mut servo_impl: T, mut receiver: Receiver<ServoControl>) -> Result<(), Error> where T: ServoImpl + Send{
-    while let Some(mut ctl) = receiver.recv().await {
-        while !ctl.should_stop() {
-            servo_impl.rotate(ctl.dx, ctl.dy);
+async fn servo_control_routine<T>(
+    mut servo_impl: T,
+    mut rigs: Vec<Rig>,
+    park: Vec<ParkSettings>,
+    idle_timeout: Option<Duration>,
+    mut receiver: Receiver<ServoControl>,
+    mut park_receiver: Receiver<oneshot::Sender<()>>,
+) where T: ServoImpl + Send {
+    let mut interval = tokio::time::interval(planner::TICK);
+    let mut parked: Option<oneshot::Sender<()>> = None;
+    let mut is_asleep = false;
+    let mut idle_at = idle_timeout.map(|timeout| Instant::now() + timeout);
+    loop {
+        let is_moving = rigs.iter().any(Rig::is_moving);
+        if !is_moving {
+            if let Some(done) = parked.take() {
+                match servo_impl.sleep() {
+                    Ok(()) => info!("Servo parked"),
+                    Err(e) => error!("Failed to put the servo to sleep: {}", e),
+                }
+                done.send(()).unwrap_or_default();
+                break;
+            }
+        }
+
+        let deadline = rigs.iter().filter_map(|rig| rig.deadline).min();
+        let idle_deadline = idle_at.filter(|_| !is_moving && !is_asleep && parked.is_none());
+        tokio::select! {
+            control = receiver.recv() => {
+                let control = match control {
+                    Some(control) => control,
+                    None => break,
+                };
+
+                if parked.is_some() {
+                    debug!(rig = control.rig(); "Ignoring servo control while parking");
+                    continue;
+                }
 
-            tokio::time::sleep(Duration::from_millis(SERVO_ROTATION_INTERVAL)).await;
+                if is_asleep {
+                    is_asleep = !wake(&mut servo_impl, &rigs);
+                }
+                if let Some(rig) = rigs.iter_mut().find(|r| r.id == control.rig()) {
+                    let was_moving = rig.is_moving();
+                    rig.control(control);
+                    if !was_moving && rig.is_moving() {
+                        metrics::servo_moved(rig.id);
+                    }
+                    rig.moving.send_replace(rig.is_moving());
+                }
 
-            ctl = receiver.try_recv().unwrap_or(ctl);
+                if !is_moving {
+                    interval.reset();
+                }
+                idle_at = idle_timeout.map(|timeout| Instant::now() + timeout);
+            },
+
+            Some(done) = park_receiver.recv(), if parked.is_none() => {
+                if is_asleep {
+                    is_asleep = !wake(&mut servo_impl, &rigs);
+                }
+                for rig in rigs.iter_mut() {
+                    let control = match park.iter().find(|position| position.rig == rig.id) {
+                        Some(position) => ServoControl::Position { rig: rig.id, pan: Some(position.pan), tilt: Some(position.tilt), speed: None },
+                        None => ServoControl::Velocity { rig: rig.id, pan: 0.0, tilt: 0.0, timeout: None },
+                    };
+                    rig.control(control);
+                    rig.moving.send_replace(rig.is_moving());
+                }
+
+                if !is_moving {
+                    interval.reset();
+                }
+                parked = Some(done);
+            },
+
+            _ = interval.tick(), if is_moving => {
+                for rig in rigs.iter_mut().filter(|r| r.is_moving()) {
+                    rig.update(planner::TICK.as_secs_f32(), &mut servo_impl);
+                }
+                idle_at = idle_timeout.map(|timeout| Instant::now() + timeout);
+            },
+
+            _ = tokio::time::sleep_until(idle_deadline.unwrap_or_else(Instant::now)), if idle_deadline.is_some() => {
+                match servo_impl.sleep() {
+                    Ok(()) => {
+                        info!("Servo is idle, sleeping until the next command");
+                        is_asleep = true;
+                    },
+                    Err(e) => {
+                        error!("Failed to put the idle servo to sleep: {}", e);
+                        idle_at = None;
+                    },
+                }
+            },
+
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                let now = Instant::now();
+                for rig in rigs.iter_mut().filter(|r| r.deadline.is_some_and(|deadline| deadline <= now)) {
+                    warn!(rig = rig.id; "No velocity update, stopping");
+                    rig.control(ServoControl::Velocity { rig: rig.id, pan: 0.0, tilt: 0.0, timeout: None });
+                }
+            },
         }
     }
+}
 
-    Ok(())
+fn wake<T>(servo_impl: &mut T, rigs: &[Rig]) -> bool where T: ServoImpl {
+    let result = rigs.iter()
+        .try_for_each(|rig| rig.apply(servo_impl))
+        .and_then(|_| servo_impl.wake().map_err(|_| Error::CommunicationFailure));
+
+    match result {
+        Ok(()) => {
+            info!("Waking servo and restoring its angles");
+            true
+        },
+        Err(e) => {
+            error!("Failed to wake servo: {}", e);
+            false
+        },
+    }
 }
 
 impl ServoControl {
-    fn should_stop(&self) -> bool {
-        self.dx == 0 && self.dy == 0
+    fn rig(&self) -> u32 {
+        match self {
+            ServoControl::Velocity { rig, .. } | ServoControl::Position { rig, .. } => *rig,
+        }
+    }
+}
+
+impl Rig {
+    fn new(settings: &RigSettings, servo_settings: &ServoSettings) -> Self {
+        let axis = |settings: &AxisSettings| {
+            let limits = Limits {
+                min_angle: settings.min_angle as f32,
+                max_angle: settings.max_angle as f32,
+                max_speed: servo_settings.max_speed,
+                acceleration: servo_settings.acceleration,
+            };
+            Axis { settings: *settings, motion: AxisMotion::new(limits, settings.home_angle as f32) }
+        };
+        let pan = settings.pan.as_ref().map(axis);
+        let tilt = settings.tilt.as_ref().map(axis);
+        let (angles, _) = watch::channel(Angles::default());
+        let (moving, _) = watch::channel(false);
+
+        let rig = Rig { id: settings.id, pan, tilt, deadline: None, angles, moving };
+        rig.angles.send_replace(rig.current_angles());
+        rig.record_angles(rig.current_angles());
+        rig
+    }
+
+    fn is_moving(&self) -> bool {
+        self.axes().any(|axis| axis.motion.is_moving())
+    }
+
+    fn control(&mut self, control: ServoControl) {
+        match control {
+            ServoControl::Velocity { pan, tilt, timeout, .. } => {
+                self.deadline = timeout.filter(|_| pan != 0.0 || tilt != 0.0).map(|timeout| Instant::now() + timeout);
+                if let Some(axis) = &mut self.pan { axis.motion.set_velocity(pan); }
+                if let Some(axis) = &mut self.tilt { axis.motion.set_velocity(tilt); }
+            },
+            ServoControl::Position { pan, tilt, speed, .. } => {
+                self.deadline = None;
+                if let (Some(axis), Some(angle)) = (&mut self.pan, pan) { axis.motion.set_target(angle, speed); }
+                if let (Some(axis), Some(angle)) = (&mut self.tilt, tilt) { axis.motion.set_target(angle, speed); }
+            },
+        }
+    }
+
+    fn update<T>(&mut self, dt: f32, servo_impl: &mut T) where T: ServoImpl {
+        for axis in self.pan.iter_mut().chain(self.tilt.iter_mut()) {
+            if axis.motion.is_moving() {
+                axis.motion.update(dt);
+                if let Err(e) = servo_impl.set_angle(axis.settings.board, axis.settings.channel, axis.motion.position()) {
+                    error!(rig = self.id, board = axis.settings.board, channel = axis.settings.channel; "Failed to rotate servo: {}", e);
+                }
+            }
+        }
+
+        let angles = self.current_angles();
+        if angles != *self.angles.borrow() {
+            self.angles.send_replace(angles);
+            self.record_angles(angles);
+        }
+
+        if !self.is_moving() {
+            debug!(rig = self.id, pan = angles.pan, tilt = angles.tilt; "Servo rig stopped");
+            self.moving.send_replace(false);
+        }
+    }
+
+    fn record_angles(&self, angles: Angles) {
+        if self.pan.is_some() {
+            metrics::servo_angle(self.id, "pan", angles.pan);
+        }
+        if self.tilt.is_some() {
+            metrics::servo_angle(self.id, "tilt", angles.tilt);
+        }
+    }
+
+    fn apply<T>(&self, servo_impl: &mut T) -> Result<(), Error> where T: ServoImpl {
+        for axis in self.axes() {
+            servo_impl.set_angle(axis.settings.board, axis.settings.channel, axis.motion.position())
+                .map_err(|_| Error::CommunicationFailure)?;
+        }
+
+        Ok(())
+    }
+
+    fn axes(&self) -> impl Iterator<Item = &Axis> {
+        self.pan.iter().chain(self.tilt.iter())
+    }
+
+    fn current_angles(&self) -> Angles {
+        let angle = |axis: &Option<Axis>| axis.as_ref().map_or(0, |a| a.motion.position().round() as u8);
+        Angles { pan: angle(&self.pan), tilt: angle(&self.tilt) }
     }
 }
 
@@ -90,6 +427,8 @@ impl Display for Error {
             Error::ServoNotEnabled => "Servo feature is not enabled",
             Error::DeviceNotAvailable => "Failed to open servo control device",
             Error::CommunicationFailure => "Error during communication with the servo device",
+            Error::UnknownRig => "There is no servo rig with this id",
+            Error::Busy => "Too many servo commands are queued",
         };
         write!(formatter, "{}", message)
     }
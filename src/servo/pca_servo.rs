@@ -1,9 +1,10 @@
-use super::{ServoImpl, Error};
+use std::path::Path;
 
-type I2c = i2c_linux::I2c<std::fs::File>;
+use super::{BoardProbe, ServoImpl, Error};
+use crate::metrics;
+use crate::settings::BoardSettings;
 
-const I2C_DEVICE : &str = "/dev/i2c-1";
-const I2C_ADDRESS : u16 = 0x40;
+type I2c = i2c_linux::I2c<std::fs::File>;
 
 const PCA9685_MODE1 : u8 = 0x0;
 const PCA9685_PRESCALE : u8 = 0xFE;
@@ -11,55 +12,78 @@ const LED0_ON_L : u8 = 0x6;
 
 const DEFAULT_PWM_FREQUENCY : f32 = 60.0;
 
-const SERVO_UP_CHANNEL : u8 = 0;
-const SERVO_DOWN_CHANNEL : u8 = 1;
-const SERVO_MAX_ANGLE : u8 = 180;
-const SERVO_UP_DEFAULT_ANGLE : u8 = 90;
-const SERVO_DOWN_DEFAULT_ANGLE : u8 = 90;
+const SERVO_MAX_ANGLE : f32 = 180.0;
 
 pub struct Pca9685Servo {
-    i2c_bus: I2c,
-    up_degree: u8,
-    down_degree: u8,
+    boards: Vec<(u8, I2c)>,
 }
 
 impl ServoImpl for Pca9685Servo {
-    fn rotate(&mut self, dx: i8, dy: i8) {
-        self.down_degree = update_degree(self.down_degree, dx);
-        self.up_degree = update_degree(self.up_degree, dy);
-
-        println!("New degrees: ({}, {})", self.down_degree, self.up_degree);
+    fn set_angle(&mut self, board: u8, channel: u8, angle: f32) -> std::io::Result<()> {
+        let i2c_bus = self.boards.iter_mut()
+            .find(|(id, _)| *id == board)
+            .map(|(_, i2c_bus)| i2c_bus)
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, format!("There is no servo board {}", board)))?;
+
+        trace!(board = board, channel = channel, angle = format!("{:.1}", angle); "Setting servo");
+        set_channel_degree(i2c_bus, channel, angle).inspect_err(|_| metrics::i2c_error(board))
+    }
 
-        if let Err(e) = set_servo_degree(&mut self.i2c_bus, self.up_degree, self.down_degree) {
-            eprintln!("Failed to rotate servo: {}", e);
+    fn sleep(&mut self) -> std::io::Result<()> {
+        for (board, i2c_bus) in &mut self.boards {
+            sleep(i2c_bus).inspect_err(|_| metrics::i2c_error(*board))?;
+            debug!(board = *board; "PCA9685 board is asleep");
         }
 
-        fn update_degree(degree: u8, update: i8) -> u8 {
-            let abs_upd = update.abs() as u8;
-            if update > 0 {
-                degree.checked_add(abs_upd).unwrap_or(SERVO_MAX_ANGLE).min(SERVO_MAX_ANGLE)
-            } else {
-                degree.checked_sub(abs_upd).unwrap_or(0)
-            }
+        Ok(())
+    }
+
+    fn wake(&mut self) -> std::io::Result<()> {
+        for (board, i2c_bus) in &mut self.boards {
+            wake(i2c_bus).inspect_err(|_| metrics::i2c_error(*board))?;
+            debug!(board = *board; "PCA9685 board is awake");
         }
+
+        Ok(())
     }
 }
 
 impl Pca9685Servo {
-    pub fn new () -> Result<Self, Error> {
-        let mut i2c_bus = I2c::from_path(I2C_DEVICE).device_unavailable()?;
-        i2c_bus.smbus_set_slave_address(I2C_ADDRESS, false).device_unavailable()?;
-             
-        reset(&mut i2c_bus).communication_failure()?;
-        set_pwm_frequency(&mut i2c_bus, DEFAULT_PWM_FREQUENCY).communication_failure()?;
+    pub fn new(device: &Path, boards: &[BoardSettings]) -> Result<Self, Error> {
+        let boards = boards.iter()
+            .map(|board| {
+                let i2c_bus = open_board(device, board.address).inspect_err(|e| {
+                    metrics::i2c_error(board.id);
+                    error!(board = board.id, address = format!("{:#04x}", board.address); "Failed to open PCA9685 board on {}: {}", device.display(), e);
+                })?;
+                info!(board = board.id, address = format!("{:#04x}", board.address); "Opened PCA9685 board on {}", device.display());
+                Ok((board.id, i2c_bus))
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok(Pca9685Servo { boards })
+    }
+}
 
-        let up_degree : u8 = SERVO_UP_DEFAULT_ANGLE;
-        let down_degree : u8 = SERVO_DOWN_DEFAULT_ANGLE;
+pub fn probe(device: &Path, address: u16) -> std::io::Result<BoardProbe> {
+    let mut i2c_bus = I2c::from_path(device)?;
+    i2c_bus.smbus_set_slave_address(address, false)?;
 
-        set_servo_degree(&mut i2c_bus, up_degree, down_degree).communication_failure()?;
+    Ok(BoardProbe {
+        mode: i2c_bus.smbus_read_byte_data(PCA9685_MODE1)?,
+        prescale: i2c_bus.smbus_read_byte_data(PCA9685_PRESCALE)?,
+        expected_prescale: prescale(DEFAULT_PWM_FREQUENCY),
+    })
+}
 
-        Ok(Pca9685Servo{i2c_bus, up_degree, down_degree})
-    }
+fn open_board(device: &Path, address: u16) -> Result<I2c, Error> {
+    let mut i2c_bus = I2c::from_path(device).device_unavailable()?;
+    i2c_bus.smbus_set_slave_address(address, false).device_unavailable()?;
+
+    reset(&mut i2c_bus).communication_failure()?;
+    set_pwm_frequency(&mut i2c_bus, DEFAULT_PWM_FREQUENCY).communication_failure()?;
+
+    Ok(i2c_bus)
 }
 
 unsafe impl Send for Pca9685Servo { }
@@ -72,7 +96,25 @@ fn reset(i2c_bus: &mut I2c) -> std::io::Result<()> {
     Ok(())
 }
 
-fn set_pwm_frequency(i2c_bus: &mut I2c, mut frequency: f32) -> std::io::Result<()> {
+fn sleep(i2c_bus: &mut I2c) -> std::io::Result<()> {
+    let mode = i2c_bus.smbus_read_byte_data(PCA9685_MODE1)?;
+    i2c_bus.smbus_write_byte_data(PCA9685_MODE1, (mode & 0x7F) | 0x10)
+}
+
+fn wake(i2c_bus: &mut I2c) -> std::io::Result<()> {
+    let mode = i2c_bus.smbus_read_byte_data(PCA9685_MODE1)?;
+    i2c_bus.smbus_write_byte_data(PCA9685_MODE1, mode & 0x6F)?;
+
+    delay(1);
+
+    if mode & 0x80 != 0 {
+        i2c_bus.smbus_write_byte_data(PCA9685_MODE1, (mode & 0x6F) | 0x80)?;
+    }
+
+    Ok(())
+}
+
+fn prescale(mut frequency: f32) -> u8 {
     frequency *= 0.9;  // Correct for overshoot in the frequency setting.
 
     let mut prescale_value = 25000000_f32;
@@ -80,7 +122,11 @@ fn set_pwm_frequency(i2c_bus: &mut I2c, mut frequency: f32) -> std::io::Result<(
     prescale_value /= frequency;
     prescale_value -= 1_f32;
 
-    let prescale = (prescale_value + 0.5).floor() as u8;
+    (prescale_value + 0.5).floor() as u8
+}
+
+fn set_pwm_frequency(i2c_bus: &mut I2c, frequency: f32) -> std::io::Result<()> {
+    let prescale = prescale(frequency);
     let old_mode = i2c_bus.smbus_read_byte_data(PCA9685_MODE1)?;
     let new_mode = (old_mode & 0x7F) | 0x10; // sleep
     i2c_bus.smbus_write_byte_data(PCA9685_MODE1, new_mode)?;
@@ -94,15 +140,8 @@ fn set_pwm_frequency(i2c_bus: &mut I2c, mut frequency: f32) -> std::io::Result<(
     Ok(())
 }
 
-fn set_servo_degree(i2c_bus: &mut I2c, up_degree: u8, down_degree: u8) -> std::io::Result<()> {
-    set_channel_degree(i2c_bus, SERVO_UP_CHANNEL, up_degree)?;
-    set_channel_degree(i2c_bus, SERVO_DOWN_CHANNEL, down_degree)?;
-
-    Ok(())
-}
-
-fn set_channel_degree(i2c_bus: &mut I2c, channel: u8, mut degree: u8) -> std::io::Result<()> {
-    degree = degree.max(0).min(SERVO_MAX_ANGLE);
+fn set_channel_degree(i2c_bus: &mut I2c, channel: u8, mut degree: f32) -> std::io::Result<()> {
+    degree = degree.clamp(0.0, SERVO_MAX_ANGLE);
 
     const PULSE_LENGTH : f64 = 1000.0 / 60.0 / 4096.0;
     
@@ -0,0 +1,183 @@
+use std::time::Duration;
+
+pub const TICK: Duration = Duration::from_millis(20);
+
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    pub min_angle: f32,
+    pub max_angle: f32,
+    pub max_speed: f32,
+    pub acceleration: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Goal {
+    Velocity(f32),
+    Position { target: f32, speed: f32 },
+}
+
+pub struct AxisMotion {
+    limits: Limits,
+    position: f32,
+    velocity: f32,
+    goal: Goal,
+}
+
+impl AxisMotion {
+    pub fn new(limits: Limits, position: f32) -> Self {
+        let position = position.clamp(limits.min_angle, limits.max_angle);
+        AxisMotion { limits, position, velocity: 0.0, goal: Goal::Velocity(0.0) }
+    }
+
+    pub fn position(&self) -> f32 {
+        self.position
+    }
+
+    pub fn set_velocity(&mut self, velocity: f32) {
+        self.goal = Goal::Velocity(velocity.clamp(-self.limits.max_speed, self.limits.max_speed));
+    }
+
+    pub fn set_target(&mut self, angle: f32, speed: Option<f32>) {
+        self.goal = Goal::Position {
+            target: angle.clamp(self.limits.min_angle, self.limits.max_angle),
+            speed: speed.map_or(self.limits.max_speed, |speed| speed.min(self.limits.max_speed)),
+        };
+    }
+
+    pub fn is_moving(&self) -> bool {
+        if self.velocity != 0.0 {
+            return true;
+        }
+
+        match self.goal {
+            Goal::Velocity(velocity) => {
+                (velocity > 0.0 && self.position < self.limits.max_angle)
+                    || (velocity < 0.0 && self.position > self.limits.min_angle)
+            },
+            Goal::Position { target, .. } => target != self.position,
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        let desired_velocity = match self.goal {
+            Goal::Velocity(velocity) => velocity,
+            Goal::Position { target, speed } => {
+                let distance = target - self.position;
+                let braking_speed = (2.0 * self.limits.acceleration * distance.abs()).sqrt();
+                distance.signum() * speed.min(braking_speed)
+            },
+        };
+
+        let max_change = self.limits.acceleration * dt;
+        self.velocity += (desired_velocity - self.velocity).clamp(-max_change, max_change);
+        let position = self.position + self.velocity * dt;
+
+        if let Goal::Position { target, .. } = self.goal {
+            let has_passed_target = (target - self.position) * (target - position) <= 0.0;
+            if has_passed_target {
+                self.position = target;
+                self.velocity = 0.0;
+                return;
+            }
+        }
+
+        self.position = position.clamp(self.limits.min_angle, self.limits.max_angle);
+        if self.position != position {
+            self.velocity = 0.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 0.02;
+    const EPSILON: f32 = 1e-3;
+    const LIMITS: Limits = Limits { min_angle: 10.0, max_angle: 170.0, max_speed: 90.0, acceleration: 180.0 };
+
+    fn run(motion: &mut AxisMotion, max_steps: usize) -> Vec<(f32, f32)> {
+        let mut steps = Vec::new();
+        while motion.is_moving() && steps.len() < max_steps {
+            motion.update(DT);
+            steps.push((motion.position, motion.velocity));
+        }
+
+        steps
+    }
+
+    #[test]
+    fn reaches_target_without_overshoot() {
+        for (start, target) in [(20.0, 150.0), (150.0, 20.0), (90.0, 91.5)] {
+            let mut motion = AxisMotion::new(LIMITS, start);
+            motion.set_target(target, None);
+            let steps = run(&mut motion, 1000);
+
+            assert!(!motion.is_moving(), "{} -> {} did not settle", start, target);
+            assert_eq!(motion.position(), target);
+            for (position, _) in steps {
+                assert!((position - start) * (target - start) >= 0.0 && (target - position) * (target - start) >= 0.0,
+                    "{} -> {} overshot at {}", start, target, position);
+            }
+        }
+    }
+
+    #[test]
+    fn respects_max_speed_and_acceleration() {
+        let mut motion = AxisMotion::new(LIMITS, 20.0);
+        motion.set_target(160.0, None);
+        let steps = run(&mut motion, 1000);
+
+        let mut previous_velocity = 0.0;
+        for (position, velocity) in steps {
+            assert!(velocity.abs() <= LIMITS.max_speed + EPSILON, "velocity {} exceeds max speed", velocity);
+            if position != 160.0 {
+                assert!((velocity - previous_velocity).abs() <= LIMITS.acceleration * DT + EPSILON,
+                    "velocity jumped from {} to {}", previous_velocity, velocity);
+            }
+            previous_velocity = velocity;
+        }
+    }
+
+    #[test]
+    fn uses_requested_speed_below_the_limit() {
+        let mut motion = AxisMotion::new(LIMITS, 20.0);
+        motion.set_target(160.0, Some(30.0));
+        let steps = run(&mut motion, 2000);
+
+        assert!(steps.iter().all(|(_, velocity)| velocity.abs() <= 30.0 + EPSILON));
+        assert_eq!(motion.position(), 160.0);
+    }
+
+    #[test]
+    fn clamps_at_limits() {
+        let mut motion = AxisMotion::new(LIMITS, 160.0);
+        motion.set_velocity(1000.0);
+        let steps = run(&mut motion, 1000);
+
+        assert!(steps.iter().all(|(position, _)| *position <= LIMITS.max_angle));
+        assert_eq!(motion.position(), LIMITS.max_angle);
+        assert_eq!(motion.velocity, 0.0);
+        assert!(!motion.is_moving());
+
+        motion.set_target(0.0, None);
+        run(&mut motion, 1000);
+        assert_eq!(motion.position(), LIMITS.min_angle);
+        assert_eq!(AxisMotion::new(LIMITS, 200.0).position(), LIMITS.max_angle);
+    }
+
+    #[test]
+    fn stops_when_velocity_goal_is_zero() {
+        let mut motion = AxisMotion::new(LIMITS, 90.0);
+        motion.set_velocity(-45.0);
+        for _ in 0..25 {
+            motion.update(DT);
+        }
+        motion.set_velocity(0.0);
+        let steps = run(&mut motion, 1000);
+
+        assert!(!motion.is_moving());
+        assert!(steps.iter().all(|(_, velocity)| *velocity <= 0.0));
+        assert!(motion.position() > LIMITS.min_angle && motion.position() < 90.0);
+    }
+}
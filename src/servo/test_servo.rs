@@ -1,11 +1,33 @@
+use std::path::Path;
+
 use super::ServoImpl;
+use crate::settings::BoardSettings;
 
-pub struct TestServo;
+pub struct TestServo {}
 
-impl ServoImpl for TestServo {
-    fn rotate(&mut self, dx: i8, dy: i8) {
-        println!("Rotating servo ({}, {})", dx, dy);
+impl TestServo {
+    pub fn new(device: &Path, boards: &[BoardSettings]) -> Self {
+        for board in boards {
+            info!("Simulating servo board {} at {:#04x} on {}", board.id, board.address, device.display());
+        }
+
+        TestServo {}
     }
 }
 
-unsafe impl Send for TestServo {}
+impl ServoImpl for TestServo {
+    fn set_angle(&mut self, board: u8, channel: u8, angle: f32) -> std::io::Result<()> {
+        trace!(board = board, channel = channel, angle = format!("{:.1}", angle); "Setting simulated servo");
+        Ok(())
+    }
+
+    fn sleep(&mut self) -> std::io::Result<()> {
+        info!("Putting simulated servo boards to sleep");
+        Ok(())
+    }
+
+    fn wake(&mut self) -> std::io::Result<()> {
+        info!("Waking simulated servo boards");
+        Ok(())
+    }
+}
@@ -0,0 +1,519 @@
+use std::convert::TryFrom;
+use std::fmt::Display;
+use std::io::{Error, ErrorKind, Result};
+use std::path::PathBuf;
+use std::time::Duration;
+use std::str::FromStr;
+
+use crate::camera::Parameter;
+use crate::config::Config;
+use crate::fs::Fs;
+use crate::log::{Level, Sink};
+
+#[derive(Clone, Debug)]
+pub struct CameraSettings {
+    pub id: u32,
+    pub rig: Option<u32>,
+    pub backend: String,
+    pub command: Option<String>,
+    pub rtsp_url: Option<String>,
+    pub motion_hook: String,
+    pub video_device: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    pub framerate: u32,
+    pub rotation: u32,
+    pub stream_port: u16,
+    pub stream_proxy_port: u16,
+    pub stream_quality: u32,
+    pub webcontrol_port: u16,
+    pub target_dir: PathBuf,
+    pub threshold: u32,
+    pub tracking: Option<TrackingSettings>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TrackingSettings {
+    pub dead_zone: f32,
+    pub max_speed: f32,
+    pub return_delay: Duration,
+    pub return_preset: Option<String>,
+    pub invert_pan: bool,
+    pub invert_tilt: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct ServoSettings {
+    pub i2c_device: PathBuf,
+    pub boards: Vec<BoardSettings>,
+    pub rigs: Vec<RigSettings>,
+    pub max_speed: f32,
+    pub acceleration: f32,
+    pub deadman_timeout: Duration,
+    pub presets: Vec<PresetSettings>,
+    pub park: Vec<ParkSettings>,
+    pub idle_timeout: Option<Duration>,
+    pub simulated: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct BoardSettings {
+    pub id: u8,
+    pub address: u16,
+}
+
+#[derive(Clone, Debug)]
+pub struct RigSettings {
+    pub id: u32,
+    pub pan: Option<AxisSettings>,
+    pub tilt: Option<AxisSettings>,
+}
+
+#[derive(Clone, Debug)]
+pub struct PresetSettings {
+    pub name: String,
+    pub rig: u32,
+    pub pan: f32,
+    pub tilt: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct ParkSettings {
+    pub rig: u32,
+    pub pan: f32,
+    pub tilt: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct PatrolSettings {
+    pub presets: Vec<PresetSettings>,
+    pub tours: Vec<TourSettings>,
+    pub resume_delay: Duration,
+}
+
+#[derive(Clone, Debug)]
+pub struct TourSettings {
+    pub name: String,
+    pub rig: u32,
+    pub waypoints: Vec<WaypointSettings>,
+}
+
+#[derive(Clone, Debug)]
+pub struct WaypointSettings {
+    pub preset: PresetSettings,
+    pub dwell: Duration,
+    pub speed: Option<f32>,
+}
+
+#[derive(Clone, Debug)]
+pub struct HistorySettings {
+    pub max_size: u64,
+    pub max_files: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct LogSettings {
+    pub level: Level,
+    pub sinks: Vec<Sink>,
+    pub max_size: u64,
+    pub max_files: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AxisSettings {
+    pub board: u8,
+    pub channel: u8,
+    pub min_angle: u8,
+    pub max_angle: u8,
+    pub home_angle: u8,
+}
+
+pub struct Settings {
+    path: PathBuf,
+    config: Config,
+}
+
+impl Settings {
+    pub fn load(fs: &Fs) -> Result<Self> {
+        let path = fs.settings_file()?;
+        let config = match Config::load(&path) {
+            Ok(config) => config,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                info!("No settings at {}, using defaults", path.display());
+                Config::new()
+            },
+            Err(e) => return Err(e),
+        };
+
+        Ok(Settings { path, config })
+    }
+
+    pub fn cameras(&self) -> Result<Vec<CameraSettings>> {
+        let sections = self.camera_sections();
+        let is_single_camera = sections.len() == 1;
+
+        sections.into_iter()
+            .enumerate()
+            .map(|(index, camera)| {
+                let index = index as u32;
+                Ok(CameraSettings {
+                    id: camera.section_value_or("camera_id", index)?,
+                    rig: match camera.value("rig")? {
+                        Some(rig) => Some(rig),
+                        None if is_single_camera => Some(0),
+                        None => None,
+                    },
+                    backend: camera.value_or("camera_backend", String::from("motion"))?,
+                    command: camera.value("camera_command")?,
+                    rtsp_url: camera.value("rtsp_url")?,
+                    motion_hook: camera.value_or("motion_hook", default_motion_hook())?,
+                    video_device: camera.value_or("videodevice", PathBuf::from("/dev/video0"))?,
+                    width: camera.value_or("width", 1920)?,
+                    height: camera.value_or("height", 1080)?,
+                    framerate: camera.value_or("framerate", 20)?,
+                    rotation: camera.value_or("rotate", 180)?,
+                    stream_port: camera.port("stream_port", 8095, index)?,
+                    stream_proxy_port: camera.port("stream_proxy_port", 6689, index)?,
+                    stream_quality: camera.value_or("stream_quality", 50)?,
+                    webcontrol_port: camera.port("webcontrol_port", 8080, index)?,
+                    target_dir: camera.value_or("target_dir", PathBuf::from("/home/pi/.motion/movies"))?,
+                    threshold: camera.value_or("threshold", 1500)?,
+                    tracking: camera.tracking()?,
+                })
+            })
+            .collect()
+    }
+
+    pub fn set_camera_parameter(&mut self, camera_id: u32, parameter: Parameter, value: i32) -> Result<()> {
+        let key = match parameter {
+            Parameter::Framerate => "framerate",
+            Parameter::Quality => "stream_quality",
+            Parameter::Threshold => "threshold",
+        };
+
+        let is_single_camera = self.config.cameras().len() <= 1;
+        let section_index = self.config.cameras().iter()
+            .enumerate()
+            .position(|(index, camera)| camera.value_or("camera_id", index as u32).is_ok_and(|id| id == camera_id));
+        let overrides_key = section_index.is_some_and(|index| !is_single_camera || self.config.cameras()[index].contains(key));
+
+        let settings_path = &self.path;
+        let config = match section_index {
+            Some(index) if overrides_key => &mut self.config.cameras_mut()[index],
+            _ => &mut self.config,
+        };
+
+        let path = config.path().map_or_else(|| settings_path.clone(), |p| p.to_owned());
+        config.set(key, value);
+        config.save(&path)
+    }
+
+    pub fn history(&self) -> Result<HistorySettings> {
+        Ok(HistorySettings {
+            max_size: self.config.value_or::<u64>("history_max_size", 1024)? * 1024,
+            max_files: self.config.value_or("history_files", 5)?,
+        })
+    }
+
+    pub fn log(&self) -> Result<LogSettings> {
+        let sinks = self.config.value_or("log_sinks", String::from("stderr"))?
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|sink| !sink.is_empty())
+            .map(|sink| sink.parse::<Sink>().map_err(|e| Error::new(ErrorKind::InvalidData, e)))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(LogSettings {
+            level: self.config.value_or("log_level", Level::Info)?,
+            sinks,
+            max_size: self.config.value_or::<u64>("log_max_size", 1024)? * 1024,
+            max_files: self.config.value_or("log_files", 5)?,
+        })
+    }
+
+    pub fn metrics_port(&self) -> Result<Option<u16>> {
+        let port = self.config.value_or("metrics_port", 0)?;
+        Ok(Some(port).filter(|port| *port != 0))
+    }
+
+    pub fn schedule(&self) -> Vec<String> {
+        self.config.values("schedule").map(str::to_owned).collect()
+    }
+
+    pub fn set_schedule(&mut self, lines: &[String]) -> Result<()> {
+        self.config.set_values("schedule", lines);
+        self.config.save(&self.path)
+    }
+
+    pub fn servo(&self) -> Result<ServoSettings> {
+        let mut boards = Vec::new();
+        for line in self.config.values("servo_board") {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let board = match fields[..] {
+                [id, address] => BoardSettings { id: parse_field(line, id)?, address: parse_address(line, address)? },
+                _ => return Err(invalid_servo_line("servo_board", line)),
+            };
+
+            if boards.iter().any(|b: &BoardSettings| b.id == board.id) {
+                return Err(Error::new(ErrorKind::InvalidData, format!("Servo board {} is defined more than once", board.id)));
+            }
+            boards.push(board);
+        }
+
+        if boards.is_empty() {
+            boards.push(BoardSettings { id: 0, address: 0x40 });
+        }
+
+        let mut rigs: Vec<RigSettings> = Vec::new();
+        for line in self.config.values("servo_axis") {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            if !(4..=7).contains(&fields.len()) {
+                return Err(invalid_servo_line("servo_axis", line));
+            }
+
+            let rig_id = parse_field(line, fields[0])?;
+            let axis = AxisSettings {
+                board: parse_field(line, fields[2])?,
+                channel: parse_field(line, fields[3])?,
+                min_angle: fields.get(4).map_or(Ok(0), |f| parse_field(line, f))?,
+                max_angle: fields.get(5).map_or(Ok(180), |f| parse_field(line, f))?,
+                home_angle: fields.get(6).map_or(Ok(90), |f| parse_field(line, f))?,
+            };
+
+            if !boards.iter().any(|b| b.id == axis.board) {
+                return Err(Error::new(ErrorKind::InvalidData, format!("Servo axis \"{}\" uses undefined board {}", line, axis.board)));
+            }
+            if axis.channel >= 16 || axis.min_angle > axis.max_angle || axis.max_angle > 180
+                || !(axis.min_angle..=axis.max_angle).contains(&axis.home_angle) {
+                return Err(invalid_servo_line("servo_axis", line));
+            }
+            let is_channel_used = rigs.iter()
+                .flat_map(|r| r.pan.iter().chain(r.tilt.iter()))
+                .any(|a| a.board == axis.board && a.channel == axis.channel);
+            if is_channel_used {
+                return Err(Error::new(ErrorKind::InvalidData, format!("Servo channel {} of board {} is used more than once", axis.channel, axis.board)));
+            }
+
+            let rig = match rigs.iter_mut().find(|r| r.id == rig_id) {
+                Some(rig) => rig,
+                None => {
+                    rigs.push(RigSettings { id: rig_id, pan: None, tilt: None });
+                    rigs.last_mut().unwrap()
+                },
+            };
+            let slot = match fields[1] {
+                "pan" => &mut rig.pan,
+                "tilt" => &mut rig.tilt,
+                _ => return Err(invalid_servo_line("servo_axis", line)),
+            };
+            if slot.replace(axis).is_some() {
+                return Err(Error::new(ErrorKind::InvalidData, format!("Servo rig {} has more than one {} axis", rig_id, fields[1])));
+            }
+        }
+
+        if rigs.is_empty() {
+            let axis = |channel| AxisSettings { board: boards[0].id, channel, min_angle: 0, max_angle: 180, home_angle: 90 };
+            rigs.push(RigSettings { id: 0, pan: Some(axis(1)), tilt: Some(axis(0)) });
+        }
+
+        let max_speed = self.config.value_or("servo_max_speed", 90.0)?;
+        let acceleration = self.config.value_or("servo_acceleration", 180.0)?;
+        if max_speed <= 0.0 || acceleration <= 0.0 {
+            return Err(Error::new(ErrorKind::InvalidData, "servo_max_speed and servo_acceleration must be positive"));
+        }
+
+        let mut presets: Vec<PresetSettings> = Vec::new();
+        for line in self.config.values("servo_preset") {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            if fields.len() != 4 {
+                return Err(invalid_servo_line("servo_preset", line));
+            }
+
+            let preset = PresetSettings {
+                name: fields[0].to_owned(),
+                rig: parse_field(line, fields[1])?,
+                pan: parse_field(line, fields[2])?,
+                tilt: parse_field(line, fields[3])?,
+            };
+            if !rigs.iter().any(|r| r.id == preset.rig) {
+                return Err(Error::new(ErrorKind::InvalidData, format!("Servo preset \"{}\" uses undefined rig {}", line, preset.rig)));
+            }
+            if presets.iter().any(|p| p.name == preset.name) {
+                return Err(Error::new(ErrorKind::InvalidData, format!("Servo preset {} is defined more than once", preset.name)));
+            }
+            presets.push(preset);
+        }
+
+        let mut park: Vec<ParkSettings> = Vec::new();
+        for line in self.config.values("servo_park") {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            if fields.len() != 3 {
+                return Err(invalid_servo_line("servo_park", line));
+            }
+
+            let position = ParkSettings {
+                rig: parse_field(line, fields[0])?,
+                pan: parse_field(line, fields[1])?,
+                tilt: parse_field(line, fields[2])?,
+            };
+            if !rigs.iter().any(|r| r.id == position.rig) {
+                return Err(Error::new(ErrorKind::InvalidData, format!("Servo park position \"{}\" uses undefined rig {}", line, position.rig)));
+            }
+            if park.iter().any(|p| p.rig == position.rig) {
+                return Err(Error::new(ErrorKind::InvalidData, format!("Servo park position for rig {} is defined more than once", position.rig)));
+            }
+            park.push(position);
+        }
+
+        Ok(ServoSettings {
+            i2c_device: self.config.value_or("servo_i2c_device", PathBuf::from("/dev/i2c-1"))?,
+            boards,
+            rigs,
+            max_speed,
+            acceleration,
+            deadman_timeout: Duration::from_millis(self.config.value_or("servo_deadman_timeout", 500)?),
+            presets,
+            park,
+            idle_timeout: Some(Duration::from_secs(self.config.value_or("servo_idle_timeout", 0)?)).filter(|timeout| !timeout.is_zero()),
+            simulated: parse_switch("servo_simulated", &self.config.value_or("servo_simulated", String::from("off"))?)?,
+        })
+    }
+
+    pub fn patrol(&self, servo: &ServoSettings) -> Result<PatrolSettings> {
+        let mut tours: Vec<TourSettings> = Vec::new();
+        for line in self.config.values("patrol_waypoint") {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            if !(3..=4).contains(&fields.len()) {
+                return Err(invalid_servo_line("patrol_waypoint", line));
+            }
+
+            let preset = servo.presets.iter()
+                .find(|p| p.name == fields[1])
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("Patrol waypoint \"{}\" uses undefined preset {}", line, fields[1])))?;
+            let waypoint = WaypointSettings {
+                preset: preset.clone(),
+                dwell: Duration::try_from_secs_f32(parse_field(line, fields[2])?)
+                    .map_err(|_| invalid_servo_line("patrol_waypoint", line))?,
+                speed: fields.get(3).map(|f| parse_field(line, f)).transpose()?,
+            };
+            if waypoint.speed.is_some_and(|speed| !speed.is_finite() || speed <= 0.0) {
+                return Err(invalid_servo_line("patrol_waypoint", line));
+            }
+
+            match tours.iter_mut().find(|t| t.name == fields[0]) {
+                Some(tour) if tour.rig != preset.rig => {
+                    return Err(Error::new(ErrorKind::InvalidData, format!("Patrol tour {} moves more than one rig", tour.name)));
+                },
+                Some(tour) => tour.waypoints.push(waypoint),
+                None => tours.push(TourSettings { name: fields[0].to_owned(), rig: preset.rig, waypoints: vec![waypoint] }),
+            }
+        }
+
+        Ok(PatrolSettings {
+            presets: servo.presets.clone(),
+            tours,
+            resume_delay: Duration::from_secs(self.config.value_or("patrol_resume_delay", 30)?),
+        })
+    }
+
+    fn camera_sections(&self) -> Vec<CameraSection<'_>> {
+        if self.config.cameras().is_empty() {
+            vec![CameraSection { section: None, main: &self.config }]
+        } else {
+            self.config.cameras().iter()
+                .map(|camera| CameraSection { section: Some(camera), main: &self.config })
+                .collect()
+        }
+    }
+}
+
+fn parse_field<T>(line: &str, field: &str) -> Result<T> where T: FromStr {
+    field.parse::<T>().map_err(|_| Error::new(ErrorKind::InvalidData, format!("Invalid value {} in \"{}\"", field, line)))
+}
+
+fn parse_address(line: &str, field: &str) -> Result<u16> {
+    match field.strip_prefix("0x").or_else(|| field.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16)
+            .map_err(|_| Error::new(ErrorKind::InvalidData, format!("Invalid address {} in \"{}\"", field, line))),
+        None => parse_field(line, field),
+    }
+}
+
+fn parse_switch(key: &str, value: &str) -> Result<bool> {
+    match value {
+        "off" => Ok(false),
+        "on" => Ok(true),
+        value => Err(Error::new(ErrorKind::InvalidData, format!("Invalid {} setting {}, expected on or off", key, value))),
+    }
+}
+
+fn default_motion_hook() -> String {
+    std::env::current_exe().ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join("eye-hook")))
+        .map(|hook| hook.display().to_string())
+        .unwrap_or_else(|| String::from("eye-hook"))
+}
+
+fn invalid_servo_line(key: &str, line: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Invalid {} setting \"{}\"", key, line))
+}
+
+struct CameraSection<'a> {
+    section: Option<&'a Config>,
+    main: &'a Config,
+}
+
+impl CameraSection<'_> {
+    fn value<T>(&self, key: &str) -> Result<Option<T>> where T: FromStr, T::Err: Display {
+        match self.section {
+            Some(camera) if camera.contains(key) => camera.value(key).map(Some),
+            _ if self.main.contains(key) => self.main.value(key).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    fn value_or<T>(&self, key: &str, default: T) -> Result<T> where T: FromStr, T::Err: Display {
+        Ok(self.value(key)?.unwrap_or(default))
+    }
+
+    fn is_on(&self, key: &str) -> Result<bool> {
+        parse_switch(key, &self.value_or(key, String::from("off"))?)
+    }
+
+    fn tracking(&self) -> Result<Option<TrackingSettings>> {
+        if !self.is_on("tracking")? {
+            return Ok(None);
+        }
+
+        let tracking = TrackingSettings {
+            dead_zone: self.value_or("tracking_dead_zone", 0.1)?,
+            max_speed: self.value_or("tracking_max_speed", 30.0)?,
+            return_delay: Duration::from_secs(self.value_or("tracking_return_delay", 30)?),
+            return_preset: self.value("tracking_preset")?,
+            invert_pan: self.is_on("tracking_invert_pan")?,
+            invert_tilt: self.is_on("tracking_invert_tilt")?,
+        };
+        if !(0.0..1.0).contains(&tracking.dead_zone) || tracking.max_speed <= 0.0 {
+            return Err(Error::new(ErrorKind::InvalidData, "tracking_dead_zone must be within 0..1 and tracking_max_speed positive"));
+        }
+
+        Ok(Some(tracking))
+    }
+
+    fn section_value_or<T>(&self, key: &str, default: T) -> Result<T> where T: FromStr, T::Err: Display {
+        match self.section {
+            Some(camera) => camera.value_or(key, default),
+            None => Ok(default),
+        }
+    }
+
+    fn port(&self, key: &str, default: u16, index: u32) -> Result<u16> {
+        if let Some(camera) = self.section.filter(|camera| camera.contains(key)) {
+            return camera.value(key);
+        }
+
+        let base = self.main.value_or(key, default)?;
+        u16::try_from(index).ok()
+            .and_then(|index| base.checked_add(index))
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("{} {} leaves no port for camera {}", key, base, index)))
+    }
+}
@@ -0,0 +1,129 @@
+use bytes::Bytes;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+
+use crate::mjpeg;
+
+const FRAME_BUFFER_SIZE: usize = 4;
+const UPSTREAM_RETRY_INTERVAL: u64 = 1000;
+const UPSTREAM_MAX_RETRY_INTERVAL: u64 = 30000;
+
+struct Upstream {
+    address: SocketAddr,
+    frames: broadcast::Sender<Bytes>,
+    is_running: AtomicBool,
+}
+
+pub struct StreamProxy {
+    port: u16,
+}
+
+impl StreamProxy {
+    pub async fn start(port: u16, upstream_port: u16) -> std::io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)).await?;
+        let (frames, _) = broadcast::channel(FRAME_BUFFER_SIZE);
+        let upstream = Arc::new(Upstream {
+            address: SocketAddr::from((Ipv4Addr::LOCALHOST, upstream_port)),
+            frames,
+            is_running: AtomicBool::new(false),
+        });
+
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, address)) => {
+                        debug!("Stream viewer connected from {}", address);
+                        let receiver = upstream.frames.subscribe();
+                        upstream.ensure_running();
+                        tokio::spawn(async move {
+                            if let Err(e) = mjpeg::serve_viewer(stream, receiver).await {
+                                debug!("Stream viewer {} disconnected: {}", address, e);
+                            }
+                        });
+                    },
+                    Err(e) => error!("Failed to accept stream viewer: {}", e),
+                }
+            }
+        });
+
+        Ok(StreamProxy { port })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+impl Upstream {
+    fn ensure_running(self: &Arc<Self>) {
+        if self.is_running.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        let upstream = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let mut retry_interval = UPSTREAM_RETRY_INTERVAL;
+                while upstream.frames.receiver_count() > 0 {
+                    let reader = tokio::select! {
+                        reader = upstream.connect() => reader,
+                        _ = upstream.unwatched() => break,
+                    };
+                    match reader {
+                        Ok(reader) => {
+                            retry_interval = UPSTREAM_RETRY_INTERVAL;
+                            tokio::select! {
+                                result = upstream.forward_frames(reader) => if let Err(e) = result {
+                                    warn!("Camera stream ended: {}", e);
+                                },
+                                _ = upstream.unwatched() => break,
+                            }
+                            tokio::time::sleep(Duration::from_millis(retry_interval)).await;
+                        },
+                        Err(e) => {
+                            if retry_interval == UPSTREAM_RETRY_INTERVAL {
+                                error!("Camera stream is unavailable: {}", e);
+                            } else {
+                                debug!("Camera stream is still unavailable, retrying in {} ms: {}", retry_interval, e);
+                            }
+                            tokio::time::sleep(Duration::from_millis(retry_interval)).await;
+                            retry_interval = (retry_interval * 2).min(UPSTREAM_MAX_RETRY_INTERVAL);
+                        },
+                    }
+                }
+
+                upstream.is_running.store(false, Ordering::Release);
+                if upstream.frames.receiver_count() == 0 || upstream.is_running.swap(true, Ordering::AcqRel) {
+                    break;
+                }
+            }
+        });
+    }
+
+    async fn unwatched(&self) {
+        while self.frames.receiver_count() > 0 {
+            tokio::time::sleep(Duration::from_millis(UPSTREAM_RETRY_INTERVAL)).await;
+        }
+    }
+
+    async fn connect(&self) -> std::io::Result<mjpeg::FrameReader<TcpStream>> {
+        let mut stream = TcpStream::connect(self.address).await?;
+        stream.write_all(b"GET / HTTP/1.0\r\n\r\n").await?;
+        mjpeg::FrameReader::new(stream).await
+    }
+
+    async fn forward_frames(&self, mut reader: mjpeg::FrameReader<TcpStream>) -> std::io::Result<()> {
+        while let Some(frame) = reader.next_frame().await? {
+            if self.frames.send(frame).is_err() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+use std::io::{Error, ErrorKind, Result};
+use tokio::sync::mpsc::{self, Sender, Receiver};
+use tokio::time::Instant;
+
+use crate::servo::Servo;
+use crate::settings::{CameraSettings, PresetSettings, TrackingSettings};
+
+const TRACKING_BUFFER_SIZE: usize = 16;
+
+struct Report {
+    camera_id: u32,
+    x: i32,
+    y: i32,
+}
+
+pub struct Tracker {
+    sender: Option<Sender<Report>>,
+    rigs: Vec<(u32, u32)>,
+}
+
+struct Target {
+    camera_id: u32,
+    rig: u32,
+    width: f32,
+    height: f32,
+    settings: TrackingSettings,
+    preset: Option<PresetSettings>,
+    return_at: Option<Instant>,
+}
+
+impl Tracker {
+    pub fn new(cameras: &[CameraSettings], presets: &[PresetSettings], servo: Option<Servo>) -> Result<Self> {
+        let mut targets = Vec::new();
+        for camera in cameras {
+            let settings = match &camera.tracking {
+                Some(settings) => settings.clone(),
+                None => continue,
+            };
+            let rig = camera.rig
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("Camera {} enables tracking without a rig", camera.id)))?;
+            let preset = settings.return_preset.as_ref()
+                .map(|name| presets.iter()
+                    .find(|preset| preset.name == *name && preset.rig == rig)
+                    .cloned()
+                    .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("Camera {} tracking preset {} is not a preset of rig {}", camera.id, name, rig))))
+                .transpose()?;
+
+            info!("Tracking motion on camera {} with rig {}", camera.id, rig);
+            targets.push(Target {
+                camera_id: camera.id,
+                rig,
+                width: camera.width as f32,
+                height: camera.height as f32,
+                settings,
+                preset,
+                return_at: None,
+            });
+        }
+
+        let rigs = targets.iter().map(|target| (target.camera_id, target.rig)).collect();
+        let sender = match servo {
+            Some(servo) if !targets.is_empty() => {
+                let (sender, receiver) = mpsc::channel(TRACKING_BUFFER_SIZE);
+                tokio::spawn(tracking_routine(servo, targets, receiver));
+                Some(sender)
+            },
+            _ => None,
+        };
+
+        Ok(Tracker { sender, rigs })
+    }
+
+    pub fn rig(&self, camera_id: u32) -> Option<u32> {
+        self.rigs.iter()
+            .find(|(id, _)| *id == camera_id)
+            .map(|(_, rig)| *rig)
+    }
+
+    pub fn report(&self, camera_id: u32, x: i32, y: i32) {
+        if let Some(sender) = &self.sender {
+            sender.try_send(Report { camera_id, x, y }).unwrap_or_default();
+        }
+    }
+}
+
+async fn tracking_routine(mut servo: Servo, mut targets: Vec<Target>, mut receiver: Receiver<Report>) {
+    loop {
+        let deadline = targets.iter().filter_map(|target| target.return_at).min();
+        tokio::select! {
+            report = receiver.recv() => match report {
+                Some(report) => {
+                    if let Some(target) = targets.iter_mut().find(|t| t.camera_id == report.camera_id) {
+                        target.track(&mut servo, report.x, report.y);
+                    }
+                },
+                None => break,
+            },
+
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                let now = Instant::now();
+                for target in targets.iter_mut().filter(|t| t.return_at.is_some_and(|at| at <= now)) {
+                    target.return_to_preset(&mut servo);
+                }
+            },
+        }
+    }
+}
+
+impl Target {
+    fn track(&mut self, servo: &mut Servo, x: i32, y: i32) {
+        let pan = self.speed(x as f32, self.width, self.settings.invert_pan);
+        let tilt = self.speed(y as f32, self.height, self.settings.invert_tilt);
+        if let Err(e) = servo.nudge(self.rig, pan, tilt) {
+            error!("Failed to track motion with rig {}: {}", self.rig, e);
+        }
+
+        if self.preset.is_some() {
+            self.return_at = Some(Instant::now() + self.settings.return_delay);
+        }
+    }
+
+    fn speed(&self, position: f32, size: f32, is_inverted: bool) -> f32 {
+        let offset = ((position - size / 2.0) / (size / 2.0)).clamp(-1.0, 1.0);
+        if offset.abs() <= self.settings.dead_zone {
+            return 0.0;
+        }
+
+        let magnitude = (offset.abs() - self.settings.dead_zone) / (1.0 - self.settings.dead_zone);
+        let direction = if is_inverted { -offset.signum() } else { offset.signum() };
+        direction * magnitude * self.settings.max_speed
+    }
+
+    fn return_to_preset(&mut self, servo: &mut Servo) {
+        self.return_at = None;
+        if let Some(preset) = &self.preset {
+            info!("No motion on camera {}, returning rig {} to preset {}", self.camera_id, self.rig, preset.name);
+            if let Err(e) = servo.move_to(self.rig, preset.pan, preset.tilt, Some(self.settings.max_speed)) {
+                error!("Failed to return rig {} to preset {}: {}", self.rig, preset.name, e);
+            }
+        }
+    }
+}